@@ -0,0 +1,327 @@
+/*!
+ * Behaviour trees for building creature brains out of small parts
+ *
+ * Every decision the tree is run from the root. Nodes either succeed
+ * or fail; action nodes succeed by putting an Action on the blackboard.
+ */
+
+use std::rand::RngUtil;
+use std::rand;
+
+use map;
+use map::MapView;
+
+#[deriving(Eq)]
+pub enum Status {
+	SUCCESS,
+	FAILURE
+}
+
+/**
+ * State shared between the nodes of a tree
+ *
+ * `target` survives between decisions, so a creature
+ * can keep chasing something it no longer sees.
+ */
+pub struct Blackboard {
+	target : Option<map::Position>,
+	action : Option<map::Action>
+}
+
+pub trait Node {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status;
+}
+
+pub struct BehaviourTree {
+	root : ~Node,
+	bb : Blackboard
+}
+
+// Longest path (in hexes) PathToward will look for
+static PATH_LIMIT: uint = 16;
+
+// Runs children until one fails
+pub struct Sequence {
+	children : ~[~Node]
+}
+
+// Runs children until one succeeds
+pub struct Selector {
+	children : ~[~Node]
+}
+
+pub struct Condition {
+	test : ~fn(map : &mut map::Map, id : map::CreatureId, bb : &Blackboard) -> bool
+}
+
+pub struct ActionNode {
+	action : ~fn(map : &mut map::Map, id : map::CreatureId, bb : &Blackboard) -> Option<map::Action>
+}
+
+// Sets target to player position if player is in sight
+pub struct SeePlayer;
+
+// Is target right next to the creature
+pub struct IsAdjacent;
+
+// Turns toward target and attacks it
+pub struct Attack;
+
+// Walks shortest path toward target
+pub struct PathToward;
+
+// Runs away from whatever threatens the creature
+pub struct Flee;
+
+pub struct Wander;
+
+// Uses item lying under the creature, if wounded
+pub struct UseItem;
+
+impl BehaviourTree {
+	pub fn new(root : ~Node) -> BehaviourTree {
+		BehaviourTree {
+			root: root,
+			bb: Blackboard { target: None, action: None }
+		}
+	}
+}
+
+impl map::MoveController for BehaviourTree {
+	fn get_move(&mut self, map : &mut map::Map, id : map::CreatureId) -> map::Action {
+		self.bb.action = None;
+		self.root.run(map, id, &mut self.bb);
+		match self.bb.action {
+			Some(action) => action,
+			None => map::WAIT
+		}
+	}
+}
+
+pub fn sequence(children : ~[~Node]) -> ~Node {
+	~Sequence { children: children } as ~Node
+}
+
+pub fn selector(children : ~[~Node]) -> ~Node {
+	~Selector { children: children } as ~Node
+}
+
+pub fn condition(test : ~fn(map : &mut map::Map, id : map::CreatureId, bb : &Blackboard) -> bool) -> ~Node {
+	~Condition { test: test } as ~Node
+}
+
+pub fn action(action : ~fn(map : &mut map::Map, id : map::CreatureId, bb : &Blackboard) -> Option<map::Action>) -> ~Node {
+	~ActionNode { action: action } as ~Node
+}
+
+impl Node for Sequence {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		for child in self.children.mut_iter() {
+			if child.run(map, id, bb) == FAILURE {
+				return FAILURE;
+			}
+		}
+		SUCCESS
+	}
+}
+
+impl Node for Selector {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		for child in self.children.mut_iter() {
+			if child.run(map, id, bb) == SUCCESS {
+				return SUCCESS;
+			}
+		}
+		FAILURE
+	}
+}
+
+impl Node for Condition {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		if (self.test)(map, id, bb) { SUCCESS } else { FAILURE }
+	}
+}
+
+impl Node for ActionNode {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		let a = (self.action)(map, id, bb);
+		act(bb, a)
+	}
+}
+
+impl Node for SeePlayer {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		map::Creature::refresh_visibility(map, id);
+
+		let cr = map.creature(id);
+		let mut seen = None;
+		do map.for_each_creature |c| {
+			if c.is_player() && cr.sees(c.pos) {
+				seen = Some(c.pos);
+			}
+		}
+		match seen {
+			Some(_) => {
+				bb.target = seen;
+				SUCCESS
+			},
+			None => FAILURE
+		}
+	}
+}
+
+impl Node for IsAdjacent {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		match bb.target {
+			Some(target) if map.distance(map.creature(id).pos, target) == 1 => SUCCESS,
+			_ => FAILURE
+		}
+	}
+}
+
+impl Node for Attack {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		let cr = map.creature(id);
+		let a = do bb.target.map |&target| {
+			let pos = cr.pos;
+			let cd = cr.dir;
+			let towards = map.direction_to(pos, target);
+			if cd == towards {
+				map::MELEE(map::FORWARD)
+			} else {
+				map::TURN(cd.turn_towards(towards))
+			}
+		};
+		act(bb, a)
+	}
+}
+
+impl Node for PathToward {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		let a = match bb.target {
+			Some(target) => {
+				let cr = map.creature(id);
+				if map.distance(cr.pos, target) == 0 {
+					None
+				} else {
+					map.path_step(cr.pos, target, PATH_LIMIT).map(|&d| step(cr, d))
+				}
+			},
+			None => None
+		};
+		// reached or out of reach, no use looking for a path again
+		if a.is_none() {
+			bb.target = None;
+		}
+		act(bb, a)
+	}
+}
+
+impl Node for Flee {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		let a = match map.creature(id).threat {
+			Some(threat) => Some(flee_from(map, id, threat)),
+			None => None
+		};
+		act(bb, a)
+	}
+}
+
+impl Node for Wander {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		act(bb, Some(wander(map, id)))
+	}
+}
+
+impl Node for UseItem {
+	fn run(&mut self, map : &mut map::Map, id : map::CreatureId, bb : &mut Blackboard) -> Status {
+		let cr = map.creature(id);
+		if cr.life < cr.max_life && cr.has_usable_item(map) {
+			act(bb, Some(map::USE))
+		} else {
+			FAILURE
+		}
+	}
+}
+
+fn act(bb : &mut Blackboard, action : Option<map::Action>) -> Status {
+	match action {
+		Some(_) => {
+			bb.action = action;
+			SUCCESS
+		},
+		None => FAILURE
+	}
+}
+
+// Move one hex in absolute direction `d`
+pub fn step(cr : &map::Creature, d : map::Direction) -> map::Action {
+	let cd = cr.dir;
+	if cd == d {
+		map::MOVE(map::FORWARD)
+	} else {
+		map::TURN(cd.turn_towards(d))
+	}
+}
+
+pub fn flee_from(map : &map::Map, id : map::CreatureId, threat : map::Position) -> map::Action {
+	let cr = map.creature(id);
+	let pos = cr.pos;
+	let cd = cr.dir;
+	let away = map.direction_to(pos, threat).turn(map::BACKWARD);
+	let front = pos.neighbor(cd);
+	let blocked = !map.at(front).is_passable() || map.creature_at(front).is_some();
+
+	if blocked {
+		map::TURN(map::LEFT)
+	} else if cd == away {
+		map::RUN(map::FORWARD)
+	} else if map.distance(front, threat) > map.distance(pos, threat) {
+		map::MOVE(map::FORWARD)
+	} else {
+		map::TURN(cd.turn_towards(away))
+	}
+}
+
+pub fn wander(map : &map::Map, id : map::CreatureId) -> map::Action {
+	let mut rng = rand::rng();
+
+	match rng.gen_int_range(0, 10) {
+		0 => map::TURN(map::LEFT),
+		1 => map::TURN(map::RIGHT),
+		_ => {
+			let cr = map.creature(id);
+			let front = cr.pos.neighbor(cr.dir);
+			let in_front = map.at(front);
+			if in_front.is_passable() {
+				map::MOVE(map::FORWARD)
+			} else {
+				map::TURN(map::LEFT)
+			}
+		}
+	}
+}
+
+/**
+ * Brain that heals when it can, runs when scared
+ * and otherwise chases the player down
+ */
+pub fn hunter() -> BehaviourTree {
+	BehaviourTree::new(selector(~[
+		sequence(~[
+			condition(|m, id, _| m.creature(id).morale < map::MORALE_BROKEN),
+			~Flee as ~Node
+		]),
+		~UseItem as ~Node,
+		sequence(~[
+			~SeePlayer as ~Node,
+			selector(~[
+				sequence(~[~IsAdjacent as ~Node, ~Attack as ~Node]),
+				~PathToward as ~Node
+			])
+		]),
+		// last place the player was seen at
+		~PathToward as ~Node,
+		~Wander as ~Node
+	]))
+}
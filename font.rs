@@ -0,0 +1,114 @@
+use std::cmp;
+
+use sdl::video;
+use sdl::Rect;
+
+/**
+ * Fixed width bitmap font
+ *
+ * The sheet holds printable ASCII characters, starting with space,
+ * GLYPHS_PER_ROW to a row. Anything else is drawn as '?'. Every color
+ * has a band of GLYPH_ROWS rows of its own, in the order of `Color`.
+ */
+pub struct Font {
+	sheet : ~video::Surface
+}
+
+pub enum Color {
+	WHITE = 0,
+	GREY,
+	RED,
+	GREEN,
+	YELLOW,
+	BLUE
+}
+
+pub static GLYPH_WIDTH: uint = 12;
+pub static GLYPH_HEIGHT: uint = 16;
+static GLYPHS_PER_ROW: uint = 16;
+static GLYPH_ROWS: uint = 6;
+static FIRST_GLYPH: uint = 32;
+static LAST_GLYPH: uint = 126;
+
+fn glyph_index(c : char) -> uint {
+	let c = c as uint;
+	if c < FIRST_GLYPH || c > LAST_GLYPH {
+		'?' as uint - FIRST_GLYPH
+	} else {
+		c - FIRST_GLYPH
+	}
+}
+
+impl Font {
+	pub fn new(sheet : ~video::Surface) -> Font {
+		Font { sheet: sheet }
+	}
+
+	// Width of `text` in pixels
+	pub fn width(&self, text : &str) -> uint {
+		text.char_len() * GLYPH_WIDTH
+	}
+
+	pub fn draw(&self, screen : &video::Surface, x : int, y : int, text : &str, color : Color) {
+		let band = (color as uint) * GLYPH_ROWS;
+		for (i, c) in text.iter().enumerate() {
+			let g = glyph_index(c);
+			let srect = Rect {
+				x: ((g % GLYPHS_PER_ROW) * GLYPH_WIDTH) as i16,
+				y: ((band + g / GLYPHS_PER_ROW) * GLYPH_HEIGHT) as i16,
+				w: GLYPH_WIDTH as u16,
+				h: GLYPH_HEIGHT as u16
+			};
+			let drect = Rect {
+				x: (x + (i * GLYPH_WIDTH) as int) as i16,
+				y: y as i16,
+				w: GLYPH_WIDTH as u16,
+				h: GLYPH_HEIGHT as u16
+			};
+			if !screen.blit_rect(self.sheet, Some(srect), Some(drect)) {
+				fail!(~"Failed blit_surface_rect")
+			}
+		}
+	}
+
+	/**
+	 * Break `text` into lines no wider than `width` pixels
+	 *
+	 * Lines are broken between words; a word too long for
+	 * a line of its own gets chopped.
+	 */
+	pub fn wrap(&self, text : &str, width : uint) -> ~[~str] {
+		let max = cmp::max(width / GLYPH_WIDTH, 1);
+		let mut lines = ~[];
+		let mut line = ~"";
+
+		for word in text.split_iter(' ') {
+			if line.is_empty() {
+				line = word.to_owned();
+			} else if line.char_len() + 1 + word.char_len() <= max {
+				line.push_char(' ');
+				line.push_str(word);
+			} else {
+				lines.push(line);
+				line = word.to_owned();
+			}
+
+			while line.char_len() > max {
+				lines.push(line.slice_chars(0, max).to_owned());
+				line = line.slice_chars(max, line.char_len()).to_owned();
+			}
+		}
+		lines.push(line);
+		lines
+	}
+
+	// Returns height of the drawn text in pixels
+	pub fn draw_wrapped(&self, screen : &video::Surface, x : int, y : int,
+			text : &str, color : Color, width : uint) -> uint {
+		let lines = self.wrap(text, width);
+		for (i, line) in lines.iter().enumerate() {
+			self.draw(screen, x, y + (i * GLYPH_HEIGHT) as int, *line, color);
+		}
+		lines.len() * GLYPH_HEIGHT
+	}
+}
@@ -0,0 +1,124 @@
+use std::hashmap::HashMap;
+use std::vec;
+
+use map;
+use bt;
+use pack;
+use monster;
+
+/**
+ * Map together with the brains of its creatures
+ *
+ * Populates the map the way every game starts, runs it one
+ * creature at a time and tells listeners what happened.
+ */
+pub struct Game {
+	map : map::Map,
+	controllers : HashMap<map::CreatureId, @mut map::MoveController>,
+	listeners : ~[@mut map::Listener]
+}
+
+impl Game {
+	pub fn new() -> Game {
+		let mut game = Game {
+			map: map::Map::new(),
+			controllers: HashMap::new(),
+			listeners: ~[]
+		};
+
+		let packs = vec::from_fn(3, |_| @mut pack::Pack::new());
+		for &pack in packs.iter() {
+			game.subscribe(pack as @mut map::Listener);
+		}
+
+		for i in range(0u, 30) {
+			let faction = match i % 10 {
+				0 => map::HUMANS,
+				1 => map::ANIMALS,
+				2..5 => map::GOBLINS,
+				_ => map::ORCS
+			};
+			let c = game.map.spawn_random_creature(false, faction);
+			let ctrl = if faction == map::ORCS && i % 2 == 0 {
+				@mut bt::hunter() as @mut map::MoveController
+			} else if faction == map::GOBLINS {
+				let pack = packs[i / 10];
+				pack.join(c);
+				@mut pack::PackController::new(pack) as @mut map::MoveController
+			} else {
+				@mut monster::MonsterController::new() as @mut map::MoveController
+			};
+			game.controllers.insert(c, ctrl);
+
+			let cr = game.map.creature_mut(c);
+			cr.species = match faction {
+				map::HUMANS => map::HUMAN,
+				map::ORCS => map::ORC,
+				map::GOBLINS => map::GOBLIN,
+				map::ANIMALS => map::WOLF
+			};
+			// poison alone never kills, see `Creature::tick_effects`
+			if faction == map::GOBLINS {
+				cr.venom = Some(map::StatusEffect::new(map::POISON, 1, 20));
+			}
+		}
+
+		do 20.times {
+			let pos = game.map.random_pos();
+			game.map.spawn_object(pos, ~map::Medkit::new() as ~map::Object )
+		}
+		do 10.times {
+			let pos = game.map.random_pos();
+			game.map.spawn_object(pos, ~map::Herb::new() as ~map::Object )
+		}
+		game.dispatch_events();
+		game
+	}
+
+	pub fn subscribe(&mut self, listener : @mut map::Listener) {
+		self.listeners.push(listener);
+	}
+
+	pub fn spawn_player(&mut self, ctrl : @mut map::MoveController) -> map::CreatureId {
+		let player = self.map.spawn_random_creature(true, map::HUMANS);
+		self.controllers.insert(player, ctrl);
+		self.dispatch_events();
+		map::Creature::update_visibility(&mut self.map, player);
+		player
+	}
+
+	/**
+	 * Let the next creature act
+	 *
+	 * Returns what `map::Map::step` does.
+	 */
+	pub fn step(&mut self) -> Option<(map::CreatureId, bool)> {
+		let woken = {
+			let controllers = &self.controllers;
+			do self.map.step |map, id| {
+				let ctrl = *controllers.get(&id);
+				ctrl.get_move(map, id)
+			}
+		};
+		match woken {
+			Some((id, redraw)) => {
+				if (redraw && self.map.creature(id).is_player()) {
+					map::Creature::refresh_visibility(&mut self.map, id);
+				}
+			},
+			None => {}
+		}
+		self.dispatch_events();
+		woken
+	}
+
+	// Let everyone interested know what happened on the map
+	fn dispatch_events(&mut self) {
+		let events = self.map.drain_events();
+		for event in events.iter() {
+			for &listener in self.listeners.iter() {
+				listener.on_event(&self.map, event);
+			}
+		}
+	}
+}
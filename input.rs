@@ -0,0 +1,631 @@
+/*!
+ * Frontend independent keyboard handling
+ *
+ * Frontends translate their key events into a `Key` and `Modifiers`
+ * and get back a `Command`, so every frontend has the same bindings.
+ * Actions typed while the player is busy wait here for their turn.
+ *
+ * Bindings come from a built-in layout, optionally changed by a
+ * config file with one binding per line:
+ *
+ *     # comment
+ *     layout absolute
+ *     [north-up]
+ *     shift+k    run up
+ *     ctrl+left  attack left
+ *
+ * `layout` replaces every binding so far with a built-in layout
+ * (`default`, `absolute` or `none`). Bindings after a `[north-up]` or
+ * `[turning]` line only work in that view, `[any]` goes back to both.
+ * Later bindings win over earlier ones.
+ *
+ * Digits are always the numpad, `kp0` to `kp9`, as terminals can't tell
+ * the two apart. `#` starts a comment, so it can't be bound.
+ */
+
+use std::io;
+use std::os;
+
+use map;
+
+#[deriving(Eq)]
+pub enum Key {
+	UP,
+	DOWN,
+	LEFT,
+	RIGHT,
+	PAGE_UP,
+	PAGE_DOWN,
+	ESCAPE,
+	// numpad or number key
+	KEYPAD(uint),
+	// lowercase letter or punctuation
+	CHAR(char)
+}
+
+#[deriving(Eq)]
+pub struct Modifiers {
+	ctrl : bool,
+	alt : bool,
+	shift : bool
+}
+
+/**
+ * What the player wants done
+ */
+pub enum Command {
+	ACT(map::Action),
+	// fire at the nearest hostile in sight
+	AIM,
+	// drop the pending action
+	CANCEL,
+	// sneaking was toggled
+	SNEAK,
+	MINIMAP,
+	// view orientation was toggled
+	ORIENTATION,
+	// positive goes back to older messages
+	SCROLL(int),
+	QUIT
+}
+
+#[deriving(Eq)]
+pub enum View {
+	ANY_VIEW,
+	TURNING,
+	NORTH_UP
+}
+
+pub enum Verb {
+	WALK,
+	// forward only, other directions walk
+	RUN,
+	TURN,
+	ATTACK,
+	SHOOT
+}
+
+pub enum Towards {
+	RELATIVE(map::RelativeDir),
+	COMPASS(map::Direction),
+	// clockwise from up on the screen
+	SCREEN(int)
+}
+
+pub enum Bound {
+	DO(Verb, Towards),
+	COMMAND(Command)
+}
+
+pub struct Binding {
+	key : Key,
+	mods : Modifiers,
+	view : View,
+	bound : Bound
+}
+
+/**
+ * Keyboard state of a frontend
+ */
+pub struct Controls {
+	bindings : ~[Binding],
+	sneaking : bool,
+	// north up view instead of the one turning with the player
+	absolute : bool,
+	// keys pressed while the player was busy, oldest first
+	typed : ~[(Key, Modifiers)],
+	// the player wants the pending action dropped
+	cancel : bool,
+	exit : bool
+}
+
+// Where frontends look for key bindings
+pub static CONFIG_FILE: &'static str = "keys.cfg";
+
+// Numpad keys clockwise from up
+static KEYPAD_DIRS: [uint, ..6] = [8, 9, 3, 2, 1, 7];
+
+impl Modifiers {
+	pub fn none() -> Modifiers {
+		Modifiers { ctrl: false, alt: false, shift: false }
+	}
+}
+
+// Replaces what the key did so far in the views the binding covers
+fn bind(bindings : &mut ~[Binding], key : Key, mods : Modifiers, view : View, bound : Bound) {
+	bindings.retain(|b| !(b.key == key && b.mods == mods && (view == ANY_VIEW || b.view == view)));
+	bindings.push(Binding { key: key, mods: mods, view: view, bound: bound });
+}
+
+// `plain` alone, Shift runs, Control attacks and Alt shoots
+fn bind_direction(bindings : &mut ~[Binding], key : Key, view : View, towards : Towards, plain : Verb) {
+	let none = Modifiers::none();
+	bind(bindings, key, none, view, DO(plain, towards));
+	bind(bindings, key, Modifiers { shift: true, ..none }, view, DO(RUN, towards));
+	bind(bindings, key, Modifiers { ctrl: true, ..none }, view, DO(ATTACK, towards));
+	bind(bindings, key, Modifiers { alt: true, ..none }, view, DO(SHOOT, towards));
+}
+
+/**
+ * Left and right always turn. Up and down step forward and back
+ * when the view turns with the player and go north and south in
+ * north up view. Numpad keys go the way they point on the screen.
+ */
+fn default_layout() -> ~[Binding] {
+	let mut b = ~[];
+	let none = Modifiers::none();
+	let commands = [
+		(ESCAPE, QUIT),
+		(CHAR('.'), ACT(map::WAIT)),
+		(CHAR(','), ACT(map::WAIT)),
+		(CHAR('u'), ACT(map::USE)),
+		(CHAR('f'), ACT(map::FIRE(map::FORWARD))),
+		(CHAR('t'), AIM),
+		(CHAR('b'), ACT(map::BLOCK)),
+		(CHAR('x'), CANCEL),
+		(CHAR('s'), SNEAK),
+		(CHAR('m'), MINIMAP),
+		(CHAR('o'), ORIENTATION),
+		(PAGE_UP, SCROLL(1)),
+		(PAGE_DOWN, SCROLL(-1))
+	];
+	for &(key, command) in commands.iter() {
+		bind(&mut b, key, none, ANY_VIEW, COMMAND(command));
+	}
+
+	let relative = [
+		(CHAR('k'), map::FORWARD, WALK),
+		(UP, map::FORWARD, WALK),
+		(CHAR('j'), map::BACKWARD, WALK),
+		(DOWN, map::BACKWARD, WALK),
+		(CHAR('h'), map::LEFT, TURN),
+		(LEFT, map::LEFT, TURN),
+		(CHAR('l'), map::RIGHT, TURN),
+		(RIGHT, map::RIGHT, TURN)
+	];
+	for &(key, dir, plain) in relative.iter() {
+		bind_direction(&mut b, key, ANY_VIEW, RELATIVE(dir), plain);
+	}
+
+	for (i, &kp) in KEYPAD_DIRS.iter().enumerate() {
+		bind_direction(&mut b, KEYPAD(kp), ANY_VIEW, SCREEN(i as int), WALK);
+	}
+	for &key in [CHAR('k'), UP].iter() {
+		bind_direction(&mut b, key, NORTH_UP, SCREEN(0), WALK);
+	}
+	for &key in [CHAR('j'), DOWN].iter() {
+		bind_direction(&mut b, key, NORTH_UP, SCREEN(3), WALK);
+	}
+	b
+}
+
+/**
+ * The default layout with the numpad and `qweasd` stepping toward
+ * compass directions whichever way the view is turned. Sneaking
+ * moves to `z`.
+ */
+fn absolute_layout() -> ~[Binding] {
+	let mut b = default_layout();
+	let letters = ['w', 'e', 'd', 's', 'a', 'q'];
+	for i in range(0u, 6) {
+		let towards = COMPASS(map::N.turn_by_int(i as int));
+		bind_direction(&mut b, KEYPAD(KEYPAD_DIRS[i]), ANY_VIEW, towards, WALK);
+		bind_direction(&mut b, CHAR(letters[i]), ANY_VIEW, towards, WALK);
+	}
+	bind(&mut b, CHAR('z'), Modifiers::none(), ANY_VIEW, COMMAND(SNEAK));
+	b
+}
+
+fn layout(name : &str) -> Option<~[Binding]> {
+	match name {
+		"default" => Some(default_layout()),
+		"absolute" => Some(absolute_layout()),
+		"none" => Some(~[]),
+		_ => None
+	}
+}
+
+// `shift+k`, `ctrl+alt+up`, `kp8`, `.` and the like
+fn parse_key(text : &str) -> Result<(Key, Modifiers), ~str> {
+	let mut mods = Modifiers::none();
+	let mut rest = text;
+	loop {
+		if rest.starts_with("shift+") {
+			mods.shift = true;
+			rest = rest.slice_from(6);
+		} else if rest.starts_with("ctrl+") {
+			mods.ctrl = true;
+			rest = rest.slice_from(5);
+		} else if rest.starts_with("alt+") {
+			mods.alt = true;
+			rest = rest.slice_from(4);
+		} else {
+			break;
+		}
+	}
+
+	let key = match rest {
+		"up" => UP,
+		"down" => DOWN,
+		"left" => LEFT,
+		"right" => RIGHT,
+		"pageup" => PAGE_UP,
+		"pagedown" => PAGE_DOWN,
+		"escape" => ESCAPE,
+		_ if rest.len() == 3 && rest.starts_with("kp") && rest.char_at(2).is_digit() => {
+			KEYPAD(rest.char_at(2) as uint - '0' as uint)
+		},
+		_ if rest.char_len() == 1 && rest.char_at(0).is_digit() => {
+			return Err(fmt!("unknown key \"%s\", digits are kp0 to kp9", text))
+		},
+		_ if rest.char_len() == 1 && !rest.char_at(0).is_uppercase() => CHAR(rest.char_at(0)),
+		_ => return Err(fmt!("unknown key \"%s\"", text))
+	};
+	Ok((key, mods))
+}
+
+fn parse_towards(text : &str) -> Option<Towards> {
+	match text {
+		"forward" => Some(RELATIVE(map::FORWARD)),
+		"backward" => Some(RELATIVE(map::BACKWARD)),
+		"left" => Some(RELATIVE(map::LEFT)),
+		"right" => Some(RELATIVE(map::RIGHT)),
+		"north" => Some(COMPASS(map::N)),
+		"northeast" => Some(COMPASS(map::NE)),
+		"southeast" => Some(COMPASS(map::SE)),
+		"south" => Some(COMPASS(map::S)),
+		"southwest" => Some(COMPASS(map::SW)),
+		"northwest" => Some(COMPASS(map::NW)),
+		"up" => Some(SCREEN(0)),
+		"up-right" => Some(SCREEN(1)),
+		"down-right" => Some(SCREEN(2)),
+		"down" => Some(SCREEN(3)),
+		"down-left" => Some(SCREEN(4)),
+		"up-left" => Some(SCREEN(5)),
+		_ => None
+	}
+}
+
+// `wait`, `toggle minimap`, `attack up-left` and the like
+fn parse_bound(words : &[&str]) -> Result<Bound, ~str> {
+	let text = words.connect(" ");
+	let command = match text.as_slice() {
+		"wait" => Some(ACT(map::WAIT)),
+		"use" => Some(ACT(map::USE)),
+		"block" => Some(ACT(map::BLOCK)),
+		"fire" => Some(ACT(map::FIRE(map::FORWARD))),
+		"fire nearest" => Some(AIM),
+		"cancel" => Some(CANCEL),
+		"toggle sneaking" => Some(SNEAK),
+		"toggle minimap" => Some(MINIMAP),
+		"toggle orientation" => Some(ORIENTATION),
+		"scroll back" => Some(SCROLL(1)),
+		"scroll forward" => Some(SCROLL(-1)),
+		"quit" => Some(QUIT),
+		_ => None
+	};
+	match command {
+		Some(c) => return Ok(COMMAND(c)),
+		None => {}
+	}
+
+	let unknown = Err(fmt!("unknown action \"%s\"", text));
+	if words.len() != 2 {
+		return unknown;
+	}
+	let verb = match words[0] {
+		"move" => WALK,
+		"run" => RUN,
+		"turn" => TURN,
+		"attack" => ATTACK,
+		"fire" => SHOOT,
+		_ => return unknown
+	};
+	match parse_towards(words[1]) {
+		Some(towards) => Ok(DO(verb, towards)),
+		None => unknown
+	}
+}
+
+// Nothing to aim at makes the key do nothing
+fn aim(map : &map::Map, player : map::CreatureId) -> Option<Command> {
+	match map.creature(player).nearest_seen_hostile(map) {
+		Some(target) => Some(ACT(map::FIRE_AT(map.creature(target).pos))),
+		None => None
+	}
+}
+
+fn act(verb : Verb, dir : map::RelativeDir) -> map::Action {
+	match verb {
+		WALK => map::MOVE(dir),
+		RUN if dir == map::FORWARD => map::RUN(dir),
+		RUN => map::MOVE(dir),
+		TURN => map::TURN(dir),
+		ATTACK => map::MELEE(dir),
+		SHOOT => map::FIRE(dir)
+	}
+}
+
+impl Controls {
+	pub fn new() -> Controls {
+		Controls {
+			bindings: default_layout(), sneaking: false, absolute: false,
+			typed: ~[], cancel: false, exit: false
+		}
+	}
+
+	/**
+	 * Default bindings changed by the config file at `path`, if there's one
+	 */
+	pub fn load(path : &Path) -> Result<Controls, ~str> {
+		let mut controls = Controls::new();
+		if !os::path_exists(path) {
+			return Ok(controls);
+		}
+		let text = match io::read_whole_file_str(path) {
+			Ok(text) => text,
+			Err(e) => return Err(e)
+		};
+		match controls.configure(text) {
+			Ok(()) => Ok(controls),
+			Err(e) => Err(fmt!("%s:%s", path.to_str(), e))
+		}
+	}
+
+	// Errors start with the line number
+	fn configure(&mut self, text : &str) -> Result<(), ~str> {
+		let mut view = ANY_VIEW;
+		for (i, line) in text.line_iter().enumerate() {
+			let words : ~[&str] = line.word_iter().collect();
+			if words.len() == 0 || words[0].starts_with("#") {
+				loop;
+			}
+			match self.configure_line(words, &mut view) {
+				Ok(()) => {},
+				Err(e) => return Err(fmt!("%u: %s", i + 1, e))
+			}
+		}
+		Ok(())
+	}
+
+	fn configure_line(&mut self, words : &[&str], view : &mut View) -> Result<(), ~str> {
+		match words[0] {
+			"[any]" => *view = ANY_VIEW,
+			"[turning]" => *view = TURNING,
+			"[north-up]" => *view = NORTH_UP,
+			"layout" if words.len() == 2 => match layout(words[1]) {
+				Some(b) => self.bindings = b,
+				None => return Err(fmt!("unknown layout \"%s\"", words[1]))
+			},
+			_ if words.len() < 2 => return Err(~"key without an action"),
+			_ => {
+				let (key, mods) = match parse_key(words[0]) {
+					Ok(k) => k,
+					Err(e) => return Err(e)
+				};
+				let bound = match parse_bound(words.slice_from(1)) {
+					Ok(b) => b,
+					Err(e) => return Err(e)
+				};
+				bind(&mut self.bindings, key, mods, *view, bound);
+			}
+		}
+		Ok(())
+	}
+
+	// Latest binding for the current view or any view. Keys with no
+	// binding for the exact modifiers held fall back to the strongest
+	// one alone: alt, then ctrl, then shift.
+	fn find(&self, key : Key, mods : Modifiers) -> Option<Bound> {
+		let view = if self.absolute { NORTH_UP } else { TURNING };
+		let none = Modifiers::none();
+		let dominant = if mods.alt {
+			Modifiers { alt: true, ..none }
+		} else if mods.ctrl {
+			Modifiers { ctrl: true, ..none }
+		} else if mods.shift {
+			Modifiers { shift: true, ..none }
+		} else {
+			none
+		};
+		for &m in [mods, dominant].iter() {
+			for b in self.bindings.rev_iter() {
+				if (b.view == view || b.view == ANY_VIEW) && b.key == key && b.mods == m {
+					return Some(b.bound);
+				}
+			}
+		}
+		None
+	}
+
+	fn action(&self, verb : Verb, towards : Towards, facing : map::Direction) -> map::Action {
+		let d = match towards {
+			RELATIVE(d) => return act(verb, d),
+			COMPASS(d) => d,
+			SCREEN(i) => {
+				let view_dir = if self.absolute { map::N } else { facing };
+				view_dir.turn_by_int(i)
+			}
+		};
+		match facing.to_relative(d) {
+			Some(rd) => act(verb, rd),
+			// nothing can be done toward the back flanks but turning
+			None => map::TURN(facing.turn_towards(d))
+		}
+	}
+
+	/**
+	 * Command for a key pressed by the player facing `facing`
+	 */
+	pub fn command(&mut self, key : Key, mods : Modifiers, facing : map::Direction) -> Option<Command> {
+		let command = match self.find(key, mods) {
+			Some(COMMAND(c)) => c,
+			Some(DO(verb, towards)) => ACT(self.action(verb, towards, facing)),
+			None => return None
+		};
+
+		match command {
+			SNEAK => self.sneaking = !self.sneaking,
+			ORIENTATION => self.absolute = !self.absolute,
+			_ => {}
+		}
+		if self.sneaking {
+			match command {
+				ACT(map::MOVE(d)) | ACT(map::RUN(d)) => return Some(ACT(map::SNEAK(d))),
+				_ => {}
+			}
+		}
+		Some(command)
+	}
+
+	/**
+	 * What a key pressed by `player` leaves for the frontend to do: an
+	 * action to take or a command to show.
+	 *
+	 * While the player is `busy` with a pending action, actions are
+	 * kept for `typed_action` instead. AIM turns into an action here,
+	 * QUIT and CANCEL are dealt with by setting `exit` and `cancel`;
+	 * QUIT gives a WAIT so a frontend waiting for an action can stop.
+	 */
+	pub fn press(&mut self, key : Key, mods : Modifiers, map : &map::Map,
+			player : map::CreatureId, busy : bool) -> Option<Command> {
+		let command = match self.command(key, mods, map.creature(player).dir) {
+			Some(c) => c,
+			None => return None
+		};
+		match command {
+			ACT(_) | AIM if busy => {
+				self.typed.push((key, mods));
+				None
+			},
+			AIM => aim(map, player),
+			QUIT => {
+				self.exit = true;
+				if busy { None } else { Some(ACT(map::WAIT)) }
+			},
+			CANCEL => {
+				if busy {
+					self.cancel = true;
+				}
+				None
+			},
+			_ => Some(command)
+		}
+	}
+
+	// First action typed ahead while the player was busy
+	pub fn typed_action(&mut self, map : &map::Map, player : map::CreatureId) -> Option<map::Action> {
+		while !self.typed.is_empty() {
+			let (key, mods) = self.typed.shift();
+			match self.press(key, mods, map, player, false) {
+				Some(ACT(a)) => return Some(a),
+				_ => {}
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::vec;
+
+	use map;
+	use super::{Controls, Modifiers, KEYPAD, UP, CHAR, ACT, DO, WALK, SCREEN, parse_key, parse_bound};
+
+	#[test]
+	fn keys() {
+		assert_eq!(parse_key("kp8"), Ok((KEYPAD(8), Modifiers::none())));
+		let mods = Modifiers { ctrl: true, alt: false, shift: true };
+		assert_eq!(parse_key("shift+ctrl+up"), Ok((UP, mods)));
+		assert_eq!(parse_key("K"), Err(~"unknown key \"K\""));
+		assert_eq!(parse_key("alt+pgup"), Err(~"unknown key \"alt+pgup\""));
+		assert_eq!(parse_key("8"), Err(~"unknown key \"8\", digits are kp0 to kp9"));
+	}
+
+	#[test]
+	fn actions() {
+		match parse_bound(&["move", "up-left"]) {
+			Ok(DO(WALK, SCREEN(5))) => {},
+			_ => fail!(~"\"move up-left\" not understood")
+		}
+		assert!(parse_bound(&["dance"]).is_err());
+		assert!(parse_bound(&["move", "sideways"]).is_err());
+		assert!(parse_bound(&["move", "up", "twice"]).is_err());
+	}
+
+	#[test]
+	fn config_errors() {
+		let mut c = Controls::new();
+		assert_eq!(c.configure("# . wait\n\nk"), Err(~"3: key without an action"));
+		assert_eq!(c.configure("k wait\nfoo wait"), Err(~"2: unknown key \"foo\""));
+		assert_eq!(c.configure("k dance"), Err(~"1: unknown action \"dance\""));
+		assert_eq!(c.configure("layout sideways"), Err(~"1: unknown layout \"sideways\""));
+	}
+
+	#[test]
+	fn later_bindings_win_in_every_view() {
+		let mut c = Controls::new();
+		assert_eq!(c.configure("[any]\nk wait"), Ok(()));
+		for &absolute in [false, true].iter() {
+			c.absolute = absolute;
+			match c.command(CHAR('k'), Modifiers::none(), map::N) {
+				Some(ACT(map::WAIT)) => {},
+				_ => fail!(fmt!("k doesn't wait with absolute %?", absolute))
+			}
+		}
+
+		// but a binding for one view leaves the others alone
+		assert_eq!(c.configure("[north-up]\nk use"), Ok(()));
+		c.absolute = false;
+		match c.command(CHAR('k'), Modifiers::none(), map::N) {
+			Some(ACT(map::WAIT)) => {},
+			_ => fail!(~"k doesn't wait when the view turns")
+		}
+	}
+
+	#[test]
+	fn actions_wait_while_busy() {
+		let (m, player) = small_map();
+		let mut c = Controls::new();
+		let none = Modifiers::none();
+		assert!(c.press(CHAR('k'), none, &m, player, true).is_none());
+		assert!(c.press(CHAR('x'), none, &m, player, true).is_none());
+		assert!(c.cancel);
+		match c.typed_action(&m, player) {
+			Some(map::MOVE(map::FORWARD)) => {},
+			_ => fail!(~"k typed ahead was lost")
+		}
+		assert!(c.typed_action(&m, player).is_none());
+	}
+
+	#[test]
+	fn aims_at_the_nearest_hostile_in_sight() {
+		let (mut m, player) = small_map();
+		let mut c = Controls::new();
+		let none = Modifiers::none();
+		let orc_pos = map::Position { x: 2, y: 4 };
+		m.spawn_creature(orc_pos, map::N, false, map::ORCS).unwrap();
+		assert!(c.press(CHAR('t'), none, &m, player, false).is_none());
+
+		m.creature_mut(player).mark_visible(orc_pos);
+		match c.press(CHAR('t'), none, &m, player, false) {
+			Some(ACT(map::FIRE_AT(p))) if p == orc_pos => {},
+			_ => fail!(~"didn't fire at the orc")
+		}
+	}
+
+	// Player in the middle of an empty map, facing north
+	fn small_map() -> (map::Map, map::CreatureId) {
+		let mut m = map::Map::with_tiles(vec::from_elem(8, vec::from_elem(8, map::FLOOR)));
+		let player = m.spawn_creature(map::Position { x: 2, y: 2 }, map::N, true, map::HUMANS);
+		(m, player.unwrap())
+	}
+
+	#[test]
+	fn comments_are_skipped() {
+		let mut c = Controls::new();
+		assert_eq!(c.configure("layout none\n# k quit\n#k quit"), Ok(()));
+		assert!(c.command(CHAR('k'), Modifiers::none(), map::N).is_none());
+	}
+}
@@ -1,9 +1,13 @@
 use std::cast;
 use std::rand;
 use std::rand::RngUtil;
-use std::cmp::Eq;
+use std::cmp;
+use std::cmp::{Eq, Ord};
 use std::ops::{Add, Sub};
+use std::uint;
+use std::util;
 use std::vec;
+use extra::priority_queue::PriorityQueue;
 
 #[deriving(Eq)]
 pub enum Direction {
@@ -28,50 +32,183 @@ pub enum RelativeDir {
 	LEFT
 }
 
+/**
+ * Side of a creature that an attack comes from
+ */
+#[deriving(Eq)]
+pub enum Side {
+	FRONT,
+	FRONT_FLANK,
+	BACK_FLANK,
+	BACK
+}
+
+/**
+ * What happens to a pending action when the creature gets hit
+ */
+pub enum Interruption {
+	CANCEL,
+	DELAY,
+	KEEP
+}
+
 pub enum Action {
 	RUN(RelativeDir),
 	MOVE(RelativeDir),
+	SNEAK(RelativeDir),
 	TURN(RelativeDir),
 	MELEE(RelativeDir),
+	FIRE(RelativeDir),
+	FIRE_AT(Position),
+	BLOCK,
 	USE,
 	WAIT
 }
 
+#[deriving(Eq)]
+pub enum EffectType {
+	POISON,
+	STUN,
+	SLOW,
+	REGENERATION
+}
+
+/**
+ * Timed status effect on a creature
+ *
+ * Lasts `ticks` ticks, `until` is set when it's applied.
+ */
+pub struct StatusEffect {
+	kind : EffectType,
+	strength : int,
+	ticks : uint,
+	until : uint
+}
+
+#[deriving(Eq)]
+pub enum Faction {
+	HUMANS = 0,
+	ORCS,
+	GOBLINS,
+	ANIMALS
+}
+
+#[deriving(Eq)]
+pub enum Attitude {
+	HOSTILE,
+	NEUTRAL,
+	FRIENDLY
+}
+
+static FACTIONS: uint = 4;
+
+// HOSTILITY[a][b] is how faction a treats faction b
+static HOSTILITY: [[Attitude, ..FACTIONS], ..FACTIONS] = [
+	/* HUMANS */  [FRIENDLY, HOSTILE, HOSTILE, NEUTRAL],
+	/* ORCS */    [HOSTILE, FRIENDLY, HOSTILE, NEUTRAL],
+	/* GOBLINS */ [HOSTILE, HOSTILE, FRIENDLY, HOSTILE],
+	/* ANIMALS */ [NEUTRAL, NEUTRAL, NEUTRAL, FRIENDLY]
+];
+
+pub enum Species {
+	HUMAN,
+	ORC,
+	GOBLIN,
+	WOLF
+}
+
+/**
+ * Which way a creature looks around
+ */
+pub enum ViewCone {
+	// only ahead
+	FRONT,
+	// ahead and to the sides
+	WIDE,
+	// everywhere
+	ALL_AROUND
+}
+
+/**
+ * Sound made by a creature of `faction`, as heard at some place
+ */
+pub struct Noise {
+	pos : Position,
+	// 1 at the edge of where it can be heard
+	loudness : int,
+	faction : Faction
+}
+
 pub trait MoveController {
-	fn get_move(&mut self, cr: @mut Creature) -> Action;
+	fn get_move(&mut self, map : &mut Map, id : CreatureId) -> Action;
 }
 
+/**
+ * Index of a creature in the map's arena
+ *
+ * Ids are never reused, so one stays valid after its creature dies.
+ */
+pub type CreatureId = uint;
+
 pub struct Creature {
-	map : @mut Map,
+	id : CreatureId,
 	pos : Position,
 	dir : Direction,
-	last_hit_time : int,
+	last_hit_at : Option<uint>,
 	life : int,
-	controller : @mut MoveController,
+	max_life : int,
+	resting : bool,
+	morale : int,
+	threat : Option<Position>,
+	heard : Option<Noise>,
+	attack : int,
+	defense : int,
 	action : Option<Action>,
-	pre_action_ticks : uint,
-	post_action_ticks : uint,
+	// when pending action happens
+	action_at : uint,
+	// ticks to recover after pending action
+	recovery_ticks : uint,
+	// when creature is ready to decide on next action
+	ready_at : uint,
+	// when creature is due to be woken up by the scheduler
+	wake_at : uint,
+	// time up to which per tick bookkeeping was done
+	last_update : uint,
+	effects : ~[StatusEffect],
+	venom : Option<StatusEffect>,
+	kills : uint,
 	map_visible : ~[ ~[ bool ] ],
 	map_known : ~[ ~[ bool ] ],
 	map_height: uint,
 	map_width: uint,
-	player: bool
+	visibility_dirty: bool,
+	leaves_corpse: bool,
+	player: bool,
+	faction: Faction,
+	species: Species
 }
 
 pub enum Tile {
 	FLOOR,
-	WALL
+	WALL,
+	SWAMP
 }
 
 static MAP_WIDTH : uint = 32;
 static MAP_HEIGHT : uint = 32;
 
 pub enum ObjectType {
-	MEDKIT
+	MEDKIT,
+	HERB,
+	CORPSE
 }
 
 pub trait Object {
 	fn get_type(&self) -> ObjectType;
+
+	fn is_usable(&self) -> bool {
+		true
+	}
 }
 
 pub struct Medkit;
@@ -88,41 +225,141 @@ impl Object for Medkit {
 	}
 }
 
+pub struct Herb;
+
+impl Herb {
+	pub fn new() -> Herb {
+		Herb
+	}
+}
+
+impl Object for Herb {
+	fn get_type(&self) -> ObjectType {
+		HERB
+	}
+}
+
+pub struct Corpse {
+	species : Species
+}
+
+impl Corpse {
+	pub fn new(species : Species) -> Corpse {
+		Corpse { species: species }
+	}
+}
+
+impl Object for Corpse {
+	fn get_type(&self) -> ObjectType {
+		CORPSE
+	}
+
+	fn is_usable(&self) -> bool {
+		false
+	}
+}
+
+/**
+ * Something that happened to an entity on the map
+ */
+pub enum Event {
+	SPAWNED(CreatureId),
+	DESPAWNED(CreatureId),
+	// attacker (if any), target, damage
+	HIT(Option<CreatureId>, CreatureId, int),
+	// attacker, target
+	MISSED(CreatureId, CreatureId),
+	// victim, killer (if any)
+	DIED(CreatureId, Option<CreatureId>),
+	USED(CreatureId, ObjectType)
+}
+
+/**
+ * Hook for systems that want to know what is going on on the map
+ */
+pub trait Listener {
+	fn on_event(&mut self, map : &Map, event : &Event);
+}
+
+/**
+ * Path of a fired projectile, kept around for a few ticks
+ * so the UI can draw it
+ */
+pub struct Projectile {
+	path : ~[Position],
+	until : uint
+}
+
+/**
+ * Game time
+ *
+ * TICKS_PER_SECOND ticks make a second of game time, and frontends
+ * let them pass at that pace in real time. A turn passes every time
+ * the player decides what to do.
+ */
+pub struct Clock {
+	ticks : uint,
+	turns : uint
+}
+
+/**
+ * Creature waiting in the schedule for its turn
+ */
+pub struct Wakeup {
+	time : uint,
+	id : CreatureId
+}
+
+/**
+ * Whole state of the simulation, as plain data
+ *
+ * Creatures live in an arena and everything refers to them by
+ * CreatureId. Dead ones stay in the arena, but not on the grid.
+ * Events pile up until someone drains them.
+ */
 pub struct Map {
 	tiles : ~[ ~[ Tile ] ],
-	creatures : ~[ ~[ Option<@mut Creature> ] ],
-	objects : ~[ ~[ @mut ~[ ~Object ] ] ],
+	creatures : ~[ Creature ],
+	grid : ~[ ~[ Option<CreatureId> ] ],
+	objects : ~[ ~[ ~[ ~Object ] ] ],
+	projectiles : ~[ Projectile ],
+	events : ~[ Event ],
+	queue : PriorityQueue<Wakeup>,
+	clock : Clock,
 	width : uint,
 	height : uint
 }
 
 pub trait MapView {
-	fn at(&mut self, pos: Position) -> Tile;
-	fn creature_at(&mut self, pos: Position) -> Option<@mut Creature>;
-	fn objects_at(&mut self, pos: Position) -> @mut ~[ ~Object ];
+	fn at(&self, pos: Position) -> Tile;
+	fn creature_at(&self, pos: Position) -> Option<CreatureId>;
+	fn objects_at<'a>(&'a self, pos: Position) -> &'a [ ~Object ];
 	fn translate(&self, pos : Position) -> Position;
 }
 
 /**
  * View of the map with rotation (dir) and offset (pos)
  */
-pub struct RelativeMap {
-	map : @mut Map,
+pub struct RelativeMap<'self> {
+	map : &'self Map,
 	pos : Position,
 	dir : Direction
 }
 
 impl Action {
-	fn pre_ticks(&self, cr: @mut Creature) -> uint {
+	fn pre_ticks(&self, map : &Map, id : CreatureId) -> uint {
 		match *self {
 			MOVE(BACKWARD) | RUN(BACKWARD) => 24u,
 			RUN(FORWARD) => 8u,
 			MOVE(_)|RUN(_) => 20u,
+			SNEAK(_) => 32u,
 			TURN(_) => 6u,
 			MELEE(_) => 2u,
+			FIRE(_) | FIRE_AT(_) => 12u,
+			BLOCK => 20u,
 			WAIT => 1u,
 			USE => {
-				if (cr.map.objects_at(cr.pos).len() != 0) {
+				if (map.creature(id).has_usable_item(map)) {
 					30u
 				} else {
 					0u
@@ -130,15 +367,18 @@ impl Action {
 			}
 		}
 	}
-	fn post_ticks(&self, cr : @mut Creature) -> uint {
+	fn post_ticks(&self, map : &Map, id : CreatureId) -> uint {
 		match *self {
 			RUN(FORWARD) => 2u,
 			MOVE(_)|RUN(_) => 4u,
+			SNEAK(_) => 6u,
 			TURN(_) => 4u,
 			MELEE(_) => 10u,
+			FIRE(_) | FIRE_AT(_) => 16u,
+			BLOCK => 0u,
 			WAIT => 0u,
 			USE => {
-				if (cr.map.objects_at(cr.pos).len() != 0) {
+				if (map.creature(id).has_usable_item(map)) {
 					30u
 				} else {
 					0u
@@ -146,6 +386,50 @@ impl Action {
 			}
 		}
 	}
+
+	fn interruption(&self) -> Interruption {
+		match *self {
+			USE | FIRE(_) | FIRE_AT(_) | WAIT => CANCEL,
+			RUN(_) | MOVE(_) | SNEAK(_) | TURN(_) => DELAY,
+			MELEE(_) | BLOCK => KEEP
+		}
+	}
+
+	// As in "move forward"
+	pub fn to_str(&self) -> ~str {
+		match *self {
+			RUN(d) => fmt!("run %s", d.to_str()),
+			MOVE(d) => fmt!("move %s", d.to_str()),
+			SNEAK(d) => fmt!("sneak %s", d.to_str()),
+			TURN(d) => fmt!("turn %s", d.to_str()),
+			MELEE(d) => fmt!("attack %s", d.to_str()),
+			FIRE(d) => fmt!("fire %s", d.to_str()),
+			FIRE_AT(_) => ~"fire",
+			BLOCK => ~"block",
+			USE => ~"use",
+			WAIT => ~"wait"
+		}
+	}
+
+	// How far (in hexes) the action can be heard, 0 being silent
+	fn loudness(&self, map : &Map, id : CreatureId) -> int {
+		match *self {
+			RUN(_) => 6,
+			MOVE(_) => 3,
+			SNEAK(_) => 1,
+			MELEE(_) => 5,
+			FIRE(_) | FIRE_AT(_) => 4,
+			BLOCK => 1,
+			TURN(_) | WAIT => 0,
+			USE => {
+				if (map.creature(id).has_usable_item(map)) {
+					2
+				} else {
+					0
+				}
+			}
+		}
+	}
 }
 
 impl RelativeDir {
@@ -157,6 +441,15 @@ impl RelativeDir {
 			LEFT => 5
 		}
 	}
+
+	pub fn to_str(&self) -> ~str {
+		match *self {
+			FORWARD => ~"forward",
+			BACKWARD => ~"backward",
+			RIGHT => ~"right",
+			LEFT => ~"left"
+		}
+	}
 }
 
 impl Direction {
@@ -193,6 +486,26 @@ impl Direction {
 		}
 	}
 
+	// Which way to turn to face `dir`
+	pub fn turn_towards(&self, dir : Direction) -> RelativeDir {
+		match dir.relative_to(*self).to_uint() {
+			0 => FORWARD,
+			1 | 2 => RIGHT,
+			_ => LEFT
+		}
+	}
+
+	// Relative direction pointing at `dir`, if there is one
+	pub fn to_relative(&self, dir : Direction) -> Option<RelativeDir> {
+		match dir.relative_to(*self).to_uint() {
+			0 => Some(FORWARD),
+			1 => Some(RIGHT),
+			3 => Some(BACKWARD),
+			5 => Some(LEFT),
+			_ => None
+		}
+	}
+
 	pub fn to_uint(&self) -> uint {
 		unsafe {
 			cast::transmute_copy(&(*self as int))
@@ -200,6 +513,123 @@ impl Direction {
 	}
 }
 
+impl Side {
+	// `from` is direction from the defender toward the attacker
+	pub fn of(from : Direction, facing : Direction) -> Side {
+		match from.relative_to(facing).to_uint() {
+			0 => FRONT,
+			1 | 5 => FRONT_FLANK,
+			2 | 4 => BACK_FLANK,
+			_ => BACK
+		}
+	}
+
+	// In percents
+	pub fn hit_chance(&self) -> int {
+		match *self {
+			FRONT => 60,
+			FRONT_FLANK => 75,
+			BACK_FLANK => 90,
+			BACK => 100
+		}
+	}
+
+	pub fn damage_bonus(&self) -> int {
+		match *self {
+			FRONT | FRONT_FLANK => 0,
+			BACK_FLANK => 1,
+			BACK => 2
+		}
+	}
+
+	pub fn is_front_arc(&self) -> bool {
+		match *self {
+			FRONT | FRONT_FLANK => true,
+			_ => false
+		}
+	}
+}
+
+impl Species {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			HUMAN => "human",
+			ORC => "orc",
+			GOBLIN => "goblin",
+			WOLF => "wolf"
+		}
+	}
+
+	// In percents of normal speed
+	pub fn speed(&self) -> uint {
+		match *self {
+			WOLF => 130,
+			_ => 100
+		}
+	}
+
+	pub fn view_radius(&self) -> uint {
+		match *self {
+			HUMAN => 10,
+			ORC => 7,
+			GOBLIN => 6,
+			WOLF => 5
+		}
+	}
+
+	pub fn view_cone(&self) -> ViewCone {
+		match *self {
+			HUMAN | ORC => FRONT,
+			GOBLIN => WIDE,
+			WOLF => ALL_AROUND
+		}
+	}
+}
+
+impl Faction {
+	pub fn attitude_to(&self, faction : Faction) -> Attitude {
+		HOSTILITY[*self as uint][faction as uint]
+	}
+}
+
+impl StatusEffect {
+	pub fn new(kind : EffectType, strength : int, ticks : uint) -> StatusEffect {
+		StatusEffect { kind: kind, strength: strength, ticks: ticks, until: 0 }
+	}
+}
+
+impl Clock {
+	pub fn new() -> Clock {
+		Clock { ticks: 0, turns: 0 }
+	}
+
+	// As in "12:03.4"
+	pub fn to_str(&self) -> ~str {
+		let tenths = self.ticks % TICKS_PER_SECOND * 10 / TICKS_PER_SECOND;
+		let seconds = self.ticks / TICKS_PER_SECOND;
+		fmt!("%u:%02u.%u", seconds / 60, seconds % 60, tenths)
+	}
+}
+
+// Reversed, so the earliest wake up is on top of the heap
+impl Ord for Wakeup {
+	fn lt(&self, w : &Wakeup) -> bool {
+		self.time > w.time
+	}
+
+	fn le(&self, w : &Wakeup) -> bool {
+		self.time >= w.time
+	}
+
+	fn gt(&self, w : &Wakeup) -> bool {
+		self.time < w.time
+	}
+
+	fn ge(&self, w : &Wakeup) -> bool {
+		self.time <= w.time
+	}
+}
+
 impl Eq for Position {
 	fn eq(&self, p : &Position) -> bool {
 		self.x == p.x && self.y == p.y
@@ -223,6 +653,27 @@ impl Sub<Position, Position> for Position {
 }
 
 impl Position {
+	/* Hex grid in cube coordinates is (x, y - x, -y) */
+	pub fn distance(&self, pos : Position) -> int {
+		let dx = pos.x - self.x;
+		let dy = pos.y - self.y;
+		(dx.abs() + dy.abs() + (dy - dx).abs()) / 2
+	}
+
+	// Hexes on a straight line to `pos`, excluding starting one
+	pub fn line_to(&self, pos : Position) -> ~[Position] {
+		let n = self.distance(pos);
+		let mut line = ~[];
+		for i in range(1, n + 1) {
+			let t = (i as float) / (n as float);
+			// nudge to break ties consistently
+			let q = lerp(self.x, pos.x, t) + 1e-6;
+			let s = lerp(-self.y, -pos.y, t) + 2e-6;
+			line.push(cube_round(q, -q - s, s));
+		}
+		line
+	}
+
 	pub fn relative_to(&self, pos : Position) -> ~Position {
 		~Position{ x: self.x - pos.x, y: self.y - pos.y}
 	}
@@ -254,6 +705,21 @@ impl Position {
 		}
 	}
 
+	// Direction of the neighbor that is closest to `pos`
+	pub fn direction_to(&self, pos : Position) -> Direction {
+		let mut best = N;
+		let mut best_dist = self.neighbor(N).distance(pos);
+		for i in range(1, 6) {
+			let d = N.turn_by_int(i);
+			let dist = self.neighbor(d).distance(pos);
+			if dist < best_dist {
+				best = d;
+				best_dist = dist;
+			}
+		}
+		best
+	}
+
 	pub fn neighbor(&self, direction : Direction) -> Position {
 		match direction {
 			N => Position { x: self.x, y: self.y - 1 },
@@ -271,25 +737,107 @@ fn modulo(x :int, m : int) -> int {
 	if r < 0 { r+m } else { r }
 }
 
+fn lerp(a : int, b : int, t : float) -> float {
+	(a as float) + ((b - a) as float) * t
+}
+
+fn cube_round(q : float, r : float, s : float) -> Position {
+	let mut rq = q.round();
+	let rr = r.round();
+	let mut rs = s.round();
+
+	let dq = (rq - q).abs();
+	let dr = (rr - r).abs();
+	let ds = (rs - s).abs();
+
+	if dq > dr && dq > ds {
+		rq = -rr - rs;
+	} else if ds > dr {
+		rs = -rq - rr;
+	}
+
+	Position { x: rq as int, y: -rs as int }
+}
+
 static PLAYER_VIEW: int = 10;
 
+pub static TICKS_PER_SECOND: uint = 50;
+
+static MELEE_DAMAGE: int = 1;
+
+// Each point of attack over defense adds this much to hit chance (in percents)
+static SKILL_HIT_BONUS: int = 5;
+static MIN_HIT_CHANCE: int = 5;
+// Chance to dodge (in percents) when ready and when busy doing something
+static DODGE_READY: int = 25;
+static DODGE_BUSY: int = 5;
+static BLOCK_REDUCTION: int = 1;
+
+// Poison and regeneration act once every EFFECT_PERIOD ticks
+static EFFECT_PERIOD: uint = 10;
+
+// Natural healing starts REGEN_DELAY ticks after last hit
+static REGEN_DELAY: uint = 100;
+static REGEN_PERIOD: uint = 120;
+static REST_REGEN_PERIOD: uint = 40;
+
+pub static MAX_MORALE: int = 100;
+// Monsters run away below MORALE_BROKEN and come back at MORALE_RECOVERED
+pub static MORALE_BROKEN: int = 40;
+pub static MORALE_RECOVERED: int = 80;
+static MORALE_HIT_LOSS: int = 30;
+// Allies dying within MORALE_RADIUS hexes
+static MORALE_ALLY_DEATH_LOSS: int = 20;
+static MORALE_RADIUS: int = 4;
+static MORALE_RECOVERY: int = 5;
+static MORALE_RECOVERY_PERIOD: uint = 20;
+
+static RANGED_RANGE: int = 8;
+static RANGED_DAMAGE: int = 2;
+// every RANGED_FALLOFF hexes projectile loses one point of damage
+static RANGED_FALLOFF: int = 4;
+static PROJECTILE_TTL: uint = 8;
+
+// Wake up time of creatures that are gone for good
+static NEVER: uint = uint::max_value;
+
+// Flinching from a hit puts off movement by HIT_DELAY ticks
+static HIT_DELAY: uint = 4;
+// Ticks to recover after dropping an action
+static CANCEL_RECOVERY: uint = 2;
+
+// Number of multiples of `period` in (from, to]
+fn multiples(from : uint, to : uint, period : uint) -> uint {
+	to / period - from / period
+}
+
 impl Creature {
-	pub fn new<T: MoveController + 'static>(
-			map : @mut Map, position : Position, direction : Direction,
-			ctr : @mut T, player : bool
-			) -> Creature {
+	pub fn new(map : &Map, id : CreatureId, position : Position, direction : Direction,
+			player : bool, faction : Faction) -> Creature {
 		Creature {
-			map: map,
-			last_hit_time: 1000,
+			id: id,
+			last_hit_at: None,
 			life: 3,
-			controller: ctr as @mut MoveController,
+			max_life: 3,
+			resting: false,
+			morale: MAX_MORALE,
+			threat: None,
+			heard: None,
+			attack: 5,
+			defense: 5,
 			pos : position, dir : direction,
-			action: None, pre_action_ticks: 0, post_action_ticks: 0,
+			action: None, action_at: map.now(), recovery_ticks: 0,
+			ready_at: map.now(), wake_at: map.now(), last_update: map.now(),
+			effects: ~[], venom: None, kills: 0,
 			map_visible: vec::from_elem(map.width, vec::from_elem(map.height, false)),
 			map_known: vec::from_elem(map.width, vec::from_elem(map.height, false)),
 			map_width: map.width,
 			map_height: map.height,
-			player: player
+			visibility_dirty: true,
+			leaves_corpse: true,
+			player: player,
+			faction: faction,
+			species: HUMAN
 		}
 	}
 
@@ -297,118 +845,561 @@ impl Creature {
 		self.player
 	}
 
-	pub fn tick(@mut self) -> bool {
+	pub fn attitude_to(&self, cr : &Creature) -> Attitude {
+		self.faction.attitude_to(cr.faction)
+	}
+
+	pub fn is_hostile_to(&self, cr : &Creature) -> bool {
+		self.attitude_to(cr) == HOSTILE
+	}
+
+	/**
+	 * Called by the scheduler when creature `id` is due: executes
+	 * pending action or asks `decide` for the next one.
+	 *
+	 * Returns true if the creature did something visible.
+	 */
+	pub fn wake(map : &mut Map, id : CreatureId,
+			decide : &fn(&mut Map, CreatureId) -> Action) -> bool {
+		let now = map.now();
 		let mut redraw = false;
 
-		self.last_hit_time += 1;
+		Creature::catch_up(map, id);
+		if !map.creature(id).alive() {
+			return true;
+		}
 
-		if (self.pre_action_ticks > 0) {
-			self.pre_action_ticks -= 1;
-		} else {
-			match (self.action) {
-				Some(action) => {
-					redraw = true;
-					match (action) {
-						RUN(d) => self.move(d),
-						MOVE(d) => self.move(d),
-						TURN(d) => self.turn(d),
-						MELEE(d) => self.melee(d),
-						USE => self.use_item(),
-						WAIT => {},
-					}
-					self.action = None
+		let action = map.creature(id).action;
+		let action_at = map.creature(id).action_at;
+		let ready_at = map.creature(id).ready_at;
+
+		match (action) {
+			Some(action) if action_at <= now => {
+				redraw = true;
+				let loudness = action.loudness(map, id);
+				match (action) {
+					RUN(d) => Creature::move(map, id, d),
+					MOVE(d) => Creature::move(map, id, d),
+					SNEAK(d) => Creature::move(map, id, d),
+					TURN(d) => map.creature_mut(id).turn(d),
+					MELEE(d) => Creature::melee(map, id, d),
+					FIRE(d) => Creature::fire(map, id, d),
+					FIRE_AT(p) => Creature::fire_at(map, id, p),
+					BLOCK => {},
+					USE => Creature::use_item(map, id),
+					WAIT => {},
 				}
-				None => {
-					if (self.post_action_ticks > 0) {
-						self.post_action_ticks -= 1;
-					} else {
-						let action = self.controller.get_move(self);
-						self.action = Some(action);
-						self.pre_action_ticks = action.pre_ticks(self);
-						self.post_action_ticks = action.post_ticks(self);
-					}
+				if loudness > 0 {
+					let pos = map.creature(id).pos;
+					let faction = map.creature(id).faction;
+					map.emit_noise(Noise {
+						pos: pos, loudness: loudness, faction: faction
+					});
+				}
+				let cr = map.creature_mut(id);
+				cr.action = None;
+				cr.ready_at = now + cr.recovery_ticks + 1;
+			}
+			None if ready_at <= now => {
+				if map.creature(id).is_player() {
+					map.clock.turns += 1;
 				}
+				let action = decide(map, id);
+				let pre_ticks = action.pre_ticks(map, id);
+				let post_ticks = action.post_ticks(map, id);
+				let cr = map.creature_mut(id);
+				cr.action = Some(action);
+				cr.resting = match action { WAIT => true, _ => false };
+				cr.action_at = now + cr.action_ticks(pre_ticks) + 1;
+				cr.recovery_ticks = cr.action_ticks(post_ticks);
 			}
+			_ => {}
 		}
+
+		Creature::reschedule(map, id);
 		redraw
 	}
 
-	pub fn turn(@mut self, rd : RelativeDir) {
-		self.dir.turn_mut(rd);
+	// Time of the next thing the creature needs to do, or that its
+	// effects do to it
+	pub fn next_wake(&self) -> uint {
+		let next = match self.action {
+			Some(_) => self.action_at,
+			None => self.ready_at
+		};
+		match self.next_pulse() {
+			Some(t) if t < next => t,
+			_ => next
+		}
+	}
+
+	fn reschedule(map : &mut Map, id : CreatureId) {
+		if map.creature(id).alive() {
+			let time = cmp::max(map.creature(id).next_wake(), map.now());
+			map.schedule(id, time);
+		}
+	}
+
+	// Ticks left until pending action happens
+	pub fn pre_action_ticks(&self, now : uint) -> uint {
+		match self.action {
+			Some(_) if self.action_at > now => self.action_at - now,
+			_ => 0
+		}
+	}
+
+	// Put off whatever the creature is about to do
+	pub fn delay(map : &mut Map, id : CreatureId, ticks : uint) {
+		let now = map.now();
+		{
+			let cr = map.creature_mut(id);
+			match cr.action {
+				Some(_) => cr.action_at += ticks,
+				None => cr.ready_at = cmp::max(cr.ready_at, now) + ticks
+			}
+		}
+		Creature::reschedule(map, id);
+	}
+
+	// Drop pending action if it didn't happen yet
+	pub fn cancel_action(map : &mut Map, id : CreatureId) {
+		let now = map.now();
+		if map.creature(id).pre_action_ticks(now) == 0 {
+			return;
+		}
+		{
+			let cr = map.creature_mut(id);
+			cr.action = None;
+			cr.ready_at = now + CANCEL_RECOVERY;
+		}
+		Creature::reschedule(map, id);
+	}
+
+	// Getting hit spoils what the creature was about to do
+	fn interrupt(map : &mut Map, id : CreatureId) {
+		let pending = map.creature(id).pre_action_ticks(map.now()) > 0;
+		let action = match map.creature(id).action {
+			Some(a) if pending => a,
+			_ => return
+		};
+		match action.interruption() {
+			CANCEL => Creature::cancel_action(map, id),
+			DELAY => Creature::delay(map, id, HIT_DELAY),
+			KEEP => {}
+		}
+	}
+
+	pub fn last_hit_time(&self, now : uint) -> uint {
+		match self.last_hit_at {
+			Some(t) => now - t,
+			None => uint::max_value
+		}
+	}
+
+	// Do per tick bookkeeping for all the ticks since last update
+	pub fn catch_up(map : &mut Map, id : CreatureId) {
+		let now = map.now();
+		let ticks = now - map.creature(id).last_update;
+		if ticks == 0 {
+			return;
+		}
+		map.creature_mut(id).last_update = now;
+
+		Creature::tick_effects(map, id, now - ticks, now);
+		if !map.creature(id).alive() {
+			return;
+		}
+
+		let cr = map.creature_mut(id);
+		let since = cr.last_hit_time(now);
+		let from = since - cmp::min(since, ticks);
+		cr.regenerate(from, since);
+		let recovered = multiples(from, since, MORALE_RECOVERY_PERIOD) as int;
+		cr.morale = cmp::min(cr.morale + recovered * MORALE_RECOVERY, MAX_MORALE);
+	}
+
+	// First tick `catch_up` has a poison or regeneration pulse to deal
+	// with, so nobody lingers half dead until their next action
+	fn next_pulse(&self) -> Option<uint> {
+		let mut next = None;
+		for e in self.effects.iter() {
+			if e.kind != POISON && e.kind != REGENERATION {
+				loop;
+			}
+			// pulses left to count are at these many ticks before the end
+			let left = e.until - cmp::min(e.until, self.last_update);
+			let pulse = left / EFFECT_PERIOD * EFFECT_PERIOD;
+			if pulse == 0 {
+				loop;
+			}
+			let t = e.until - pulse + 1;
+			next = match next {
+				Some(n) if n <= t => Some(n),
+				_ => Some(t)
+			};
+		}
+		next
 	}
 
-	pub fn move(@mut self, rd : RelativeDir) {
-		let d = self.dir.turn_m(rd); // workaround bug
-		let pos = self.pos; // workaround bug
-		let new_position = pos.neighbor(d);
-		self.mark_known(new_position);
-		if (self.map.at(new_position).is_passable()) {
-			self.map.move_creature(self, new_position);
+	// Effects pulse every EFFECT_PERIOD ticks before they wear off;
+	// count pulses between `from` and `to`
+	fn tick_effects(map : &mut Map, id : CreatureId, from : uint, to : uint) {
+		let mut damage = 0;
+		let mut healing = 0;
+		{
+			let cr = map.creature_mut(id);
+			for e in cr.effects.iter() {
+				let left_to = e.until - cmp::min(to, e.until);
+				let left_from = e.until - cmp::min(from, e.until);
+				let pulses = multiples(left_to, left_from, EFFECT_PERIOD) as int;
+				match e.kind {
+					POISON => damage += pulses * e.strength,
+					REGENERATION => healing += pulses * e.strength,
+					_ => {}
+				}
+			}
+			cr.effects.retain(|e| e.until > to);
+			// poison wears a creature down but never finishes it off
+			damage = cmp::min(damage, cr.life - 1);
 		}
+		Creature::hit(map, id, damage, None);
+		map.creature_mut(id).heal(healing);
 	}
 
-	pub fn use_item(@mut self) {
-		let objs = self.map.objects_at(self.pos);
+	// Applying effect that is already active refreshes it
+	pub fn apply_effect(map : &mut Map, id : CreatureId, eff : StatusEffect) {
+		Creature::catch_up(map, id);
+		match eff.kind {
+			STUN => Creature::delay(map, id, eff.strength as uint),
+			_ => {}
+		}
+		let until = map.now() + eff.ticks;
+		{
+			let cr = map.creature_mut(id);
+			let mut refreshed = false;
+			for e in cr.effects.mut_iter() {
+				if e.kind == eff.kind {
+					e.until = cmp::max(e.until, until);
+					e.strength = cmp::max(e.strength, eff.strength);
+					refreshed = true;
+				}
+			}
+			if !refreshed {
+				cr.effects.push(StatusEffect { until: until, .. eff });
+			}
+		}
+		Creature::reschedule(map, id);
+	}
 
-		if objs.len() == 0 {
+	// Ticks until effect wears off
+	pub fn effect_ticks_left(&self, e : &StatusEffect, now : uint) -> uint {
+		e.until - cmp::min(e.until, now)
+	}
+
+	pub fn has_effect(&self, kind : EffectType) -> bool {
+		self.effects.iter().any(|e| e.kind == kind)
+	}
+
+	// Scale action ticks by species speed and SLOW effect strength (in percents)
+	fn action_ticks(&self, ticks : uint) -> uint {
+		let mut percent = 100;
+		for e in self.effects.iter() {
+			match e.kind {
+				SLOW => percent += e.strength,
+				_ => {}
+			}
+		}
+		ticks * (percent as uint) / self.species.speed()
+	}
+
+	pub fn heal(&mut self, life : int) {
+		if life <= 0 {
 			return;
 		}
-		match objs.last().get_type() {
-			MEDKIT => {
-				self.life = self.life + 1;
-				objs.pop();
+		self.life = cmp::min(self.life + life, self.max_life);
+	}
+
+	// Slow natural healing, faster when resting. Last hit was
+	// `to` ticks ago and last update `from` ticks after it.
+	fn regenerate(&mut self, from : uint, to : uint) {
+		let period = if self.resting { REST_REGEN_PERIOD } else { REGEN_PERIOD };
+		if to > REGEN_DELAY {
+			self.heal(multiples(cmp::max(from, REGEN_DELAY), to, period) as int);
+		}
+	}
+
+	pub fn turn(&mut self, rd : RelativeDir) {
+		self.dir.turn_mut(rd);
+		self.visibility_dirty = true;
+	}
+
+	pub fn move(map : &mut Map, id : CreatureId, rd : RelativeDir) {
+		let new_position = {
+			let cr = map.creature_mut(id);
+			let pos = cr.pos.neighbor(cr.dir.turn(rd));
+			cr.mark_known(pos);
+			pos
+		};
+		if (map.at(new_position).is_passable()) {
+			map.move_creature(id, new_position);
+			map.creature_mut(id).visibility_dirty = true;
+			if map.creature(id).pos == map.wrap_position(new_position) {
+				let tile = map.at(new_position);
+				Creature::enter(map, id, tile);
 			}
 		}
 	}
-	pub fn melee(@mut self, rd : RelativeDir) {
-		let pos = self.pos; // workaround bug
-		let dir = self.dir;
-		let new_position = pos.neighbor(dir.turn(rd));
-		match self.map.creature_at(new_position) {
-			Some(cr) => {
-				cr.hit();
+
+	// Hazards of a tile just entered
+	fn enter(map : &mut Map, id : CreatureId, tile : Tile) {
+		match tile {
+			SWAMP => Creature::apply_effect(map, id, StatusEffect::new(SLOW, 50, 30)),
+			_ => {}
+		}
+	}
+
+	pub fn has_usable_item(&self, map : &Map) -> bool {
+		map.objects_at(self.pos).iter().any(|o| o.is_usable())
+	}
+
+	// Use topmost usable object lying under the creature
+	pub fn use_item(map : &mut Map, id : CreatureId) {
+		let pos = map.creature(id).pos;
+		let obj = {
+			let objs = map.objects_at_mut(pos);
+
+			let mut i = objs.len();
+			while i > 0 && !objs[i - 1].is_usable() {
+				i -= 1;
+			}
+			if i == 0 {
+				return;
+			}
+			objs.remove(i - 1)
+		};
+
+		map.emit(USED(id, obj.get_type()));
+		match obj.get_type() {
+			MEDKIT => map.creature_mut(id).heal(1),
+			HERB => Creature::apply_effect(map, id, StatusEffect::new(REGENERATION, 1, 20)),
+			CORPSE => {}
+		}
+	}
+
+	pub fn melee(map : &mut Map, id : CreatureId, rd : RelativeDir) {
+		let now = map.now();
+		let pos = map.creature(id).pos;
+		let dir = map.creature(id).dir.turn(rd);
+		let venom = map.creature(id).venom;
+		match map.creature_at(pos.neighbor(dir)) {
+			Some(target) => {
+				let side = Side::of(dir.turn(BACKWARD), map.creature(target).dir);
+				map.creature_mut(target).threat = Some(pos);
+				let hits = map.creature(id).rolls_to_hit(map.creature(target), side);
+				let damage = if hits && !map.creature(target).dodges(side, now) {
+					map.creature(target).blocked(side, MELEE_DAMAGE + side.damage_bonus())
+				} else {
+					0
+				};
+				if damage > 0 {
+					Creature::hit(map, target, damage, Some(id));
+					if side == BACK {
+						Creature::apply_effect(map, target, StatusEffect::new(STUN, 10, 10));
+					}
+					match venom {
+						Some(eff) => Creature::apply_effect(map, target, eff),
+						None => {}
+					}
+				} else {
+					map.emit(MISSED(id, target));
+				}
 			},
 			None => {}
 		}
 	}
 
-	pub fn hit(@mut self) {
-		self.last_hit_time = 0;
-		self.life -= 1;
+	pub fn fire(map : &mut Map, id : CreatureId, rd : RelativeDir) {
+		let mut pos = map.creature(id).pos;
+		let dir = map.creature(id).dir.turn(rd);
+		let path = do vec::from_fn(RANGED_RANGE as uint) |_| {
+			pos = pos.neighbor(dir);
+			pos
+		};
+		Creature::shoot(map, id, path);
+	}
+
+	// Shoot along the hex line to `target`, the short way around the map
+	pub fn fire_at(map : &mut Map, id : CreatureId, target : Position) {
+		let pos = map.creature(id).pos;
+		let mut path = pos.line_to(map.closest(pos, target));
+		path.truncate(RANGED_RANGE as uint);
+		Creature::shoot(map, id, path);
+	}
+
+	// Fly the projectile along `path` until it hits a wall or a creature
+	fn shoot(map : &mut Map, id : CreatureId, path : ~[Position]) {
+		let now = map.now();
+		let start = map.creature(id).pos;
+		let mut prev = start;
+		let mut flown = ~[];
 
-		if (self.life <= 0) {
-			self.die();
+		for &pos in path.iter() {
+			if !map.at(pos).is_passable() {
+				break;
+			}
+			flown.push(pos);
+			match map.creature_at(pos) {
+				Some(target) => {
+					let side = Side::of(pos.direction_to(prev), map.creature(target).dir);
+					map.creature_mut(target).threat = Some(start);
+					let damage = RANGED_DAMAGE - (start.distance(pos) - 1) / RANGED_FALLOFF;
+					let damage = if map.creature(target).dodges(side, now) {
+						0
+					} else {
+						map.creature(target).blocked(side, damage)
+					};
+					if damage > 0 {
+						Creature::hit(map, target, damage, Some(id));
+					} else {
+						map.emit(MISSED(id, target));
+					}
+					break;
+				},
+				None => {}
+			}
+			prev = pos;
+		}
+
+		map.projectiles.push(Projectile { path: flown, until: now + PROJECTILE_TTL });
+	}
+
+	fn rolls_to_hit(&self, target : &Creature, side : Side) -> bool {
+		let mut rng = rand::rng();
+		let chance = side.hit_chance() + (self.attack - target.defense) * SKILL_HIT_BONUS;
+		rng.gen_int_range(0, 100) < cmp::max(chance, MIN_HIT_CHANCE)
+	}
+
+	// Attacks from behind can't be seen coming
+	pub fn dodges(&self, side : Side, now : uint) -> bool {
+		let mut rng = rand::rng();
+		let chance = match side {
+			BACK => 0,
+			_ => if self.pre_action_ticks(now) > 0 { DODGE_BUSY } else { DODGE_READY }
+		};
+		rng.gen_int_range(0, 100) < chance
+	}
+
+	pub fn is_blocking(&self) -> bool {
+		match self.action {
+			Some(BLOCK) => true,
+			_ => false
+		}
+	}
+
+	// Damage left after blocking an attack from `side`
+	pub fn blocked(&self, side : Side, damage : int) -> int {
+		if self.is_blocking() && side.is_front_arc() {
+			cmp::max(damage - BLOCK_REDUCTION, 0)
+		} else {
+			damage
+		}
+	}
+
+	// Remember the loudest noise until the controller takes notice of it
+	pub fn hear(&mut self, noise : Noise) {
+		match self.heard {
+			Some(n) if n.loudness >= noise.loudness => {},
+			_ => self.heard = Some(noise)
+		}
+	}
+
+	// The dead can't be hurt any more, even by effects applied
+	// by the very blow that killed them
+	pub fn hit(map : &mut Map, id : CreatureId, damage : int, by : Option<CreatureId>) {
+		if damage <= 0 || !map.creature(id).alive() {
+			return;
+		}
+		// healing and effects up to now come before the blow
+		Creature::catch_up(map, id);
+		if !map.creature(id).alive() {
+			return;
+		}
+		map.emit(HIT(by, id, damage));
+		let now = map.now();
+		{
+			let cr = map.creature_mut(id);
+			cr.last_hit_at = Some(now);
+			cr.life -= damage;
+			cr.morale = cmp::max(cr.morale - MORALE_HIT_LOSS, 0);
+		}
+
+		if (map.creature(id).life <= 0) {
+			Creature::die(map, id, by);
+		} else {
+			Creature::interrupt(map, id);
 		}
 	}
 
-	pub fn die(@mut self) {
-		self.map.remove_creature(self);
+	pub fn die(map : &mut Map, id : CreatureId, killer : Option<CreatureId>) {
+		map.emit(DIED(id, killer));
+		map.despawn(id);
+		match killer {
+			Some(k) => map.creature_mut(k).kills += 1,
+			None => {}
+		}
+
+		let pos = map.creature(id).pos;
+		let faction = map.creature(id).faction;
+		let threat = map.creature(id).threat;
+		let mut allies = ~[];
+		{
+			let m : &Map = map;
+			do m.for_each_creature |cr| {
+				if cr.faction == faction && m.distance(cr.pos, pos) <= MORALE_RADIUS {
+					allies.push(cr.id);
+				}
+			}
+		}
+		for &ally in allies.iter() {
+			let cr = map.creature_mut(ally);
+			cr.morale = cmp::max(cr.morale - MORALE_ALLY_DEATH_LOSS, 0);
+			if cr.threat.is_none() {
+				cr.threat = threat;
+			}
+		}
 	}
 
 	pub fn alive(&self) -> bool {
 		self.life > 0
 	}
 
+	fn wrap_position(&self, pos : Position) -> Position {
+		Position {
+			x: modulo(pos.x, self.map_width as int),
+			y: modulo(pos.y, self.map_height as int)
+		}
+	}
+
 	pub fn mark_visible(&mut self, pos : Position) {
-		let p = self.map.wrap_position(pos);
+		let p = self.wrap_position(pos);
 
 		self.map_visible[p.x][p.y] = true;
 	}
 
 	pub fn mark_known(&mut self, pos : Position) {
-		let p = self.map.wrap_position(pos);
+		let p = self.wrap_position(pos);
 
 		self.map_known[p.x][p.y] = true;
 	}
 
 	pub fn sees(&self, pos: Position) -> bool {
-		let p = self.map.wrap_position(pos);
+		let p = self.wrap_position(pos);
 
 		self.map_visible[p.x][p.y]
 	}
 
 	pub fn knows(&self, pos: Position) -> bool {
-		let p = self.map.wrap_position(pos);
+		let p = self.wrap_position(pos);
 
 		self.map_known[p.x][p.y]
 	}
@@ -422,58 +1413,112 @@ impl Creature {
 		Position{x:0,y:0}.each_around(PLAYER_VIEW, 2, PLAYER_VIEW, PLAYER_VIEW, f)
 	}
 
-	// Very hacky, recursive LoS algorithm
-	fn do_view(&mut self, pos: Position,
-		main_dir : Direction, dir : Option<Direction>, pdir : Option<Direction>, depth: uint) {
-		if (depth == 0) {
-			return;
+	// Hexes in sight from where the creature stands
+	fn view(&self, map : &Map) -> ~[ ~[ bool ] ] {
+		let mut visible = vec::from_elem(map.width, vec::from_elem(map.height, false));
+
+		let position = self.pos;
+		let direction = self.dir;
+		let radius = self.species.view_radius();
+		let dirs = match self.species.view_cone() {
+			FRONT => ~[direction],
+			WIDE => ~[direction, direction.turn(LEFT), direction.turn(RIGHT)],
+			ALL_AROUND => vec::from_fn(6, |i| direction.turn_by_int(i as int))
+		};
+
+		for &d in dirs.iter() {
+			do_view(map, &mut visible, position, d, None, None, radius);
 		}
+		visible
+	}
 
-		self.mark_visible(pos);
-		self.mark_known(pos);
+	pub fn update_visibility(map : &mut Map, id : CreatureId) {
+		let visible = map.creature(id).view(map);
 
-		let neighbors = match (dir, pdir) {
-			(Some(dir), Some(pdir)) => {
-				if dir == pdir {
-					~[dir]
-				} else {
-					~[dir, pdir]
+		let cr = map.creature_mut(id);
+		for x in range(0, cr.map_width) {
+			for y in range(0, cr.map_height) {
+				if visible[x][y] {
+					cr.map_known[x][y] = true;
 				}
-			},
-			(Some(dir), None) => {
-				if main_dir == dir {
-					~[dir, dir.turn(LEFT), dir.turn(RIGHT)]
-				} else {
-					~[dir, main_dir]
-				}
-			},
-			_ => {
-				~[main_dir, main_dir.turn(LEFT), main_dir.turn(RIGHT)]
 			}
-		};
+		}
+		cr.map_visible = visible;
+		cr.visibility_dirty = false;
+	}
 
-		if self.map.at(pos).can_see_through() {
-			for &d in neighbors.iter() {
-				let n = pos.neighbor(d);
-				match dir {
-					Some(_) => {
-						self.do_view(n, d, Some(d), dir, depth - 1);
-					},
-					None => {
-						self.do_view(n, main_dir, Some(d), dir, depth - 1);
-					}
-				};
+	// Recalculate visibility only if it could have changed since last time
+	pub fn refresh_visibility(map : &mut Map, id : CreatureId) {
+		if map.creature(id).visibility_dirty {
+			Creature::update_visibility(map, id);
+		}
+	}
+
+	// Closest hostile creature in sight
+	pub fn nearest_visible_hostile(map : &mut Map, id : CreatureId) -> Option<CreatureId> {
+		Creature::refresh_visibility(map, id);
+		let m : &Map = map;
+		m.creature(id).nearest_seen_hostile(m)
+	}
+
+	// Same, as of the last time visibility was updated
+	pub fn nearest_seen_hostile(&self, map : &Map) -> Option<CreatureId> {
+		let mut nearest = None;
+		let mut nearest_dist = 0;
+		do map.for_each_creature |cr| {
+			let dist = map.distance(self.pos, cr.pos);
+			if self.is_hostile_to(cr) && self.sees(cr.pos)
+				&& (nearest.is_none() || dist < nearest_dist) {
+				nearest = Some(cr.id);
+				nearest_dist = dist;
 			}
 		}
+		nearest
 	}
+}
 
-	pub fn update_visibility(&mut self) {
-		self.map_visible = vec::from_elem(self.map.width, vec::from_elem(self.map.height, false));
+// Very hacky, recursive LoS algorithm
+fn do_view(map : &Map, visible : &mut ~[ ~[ bool ] ], pos: Position,
+	main_dir : Direction, dir : Option<Direction>, pdir : Option<Direction>, depth: uint) {
+	if (depth == 0) {
+		return;
+	}
 
-		let position = self.pos;
-		let direction = self.dir;
+	let p = map.wrap_position(pos);
+	visible[p.x][p.y] = true;
 
-		self.do_view(position, direction, None, None, PLAYER_VIEW as uint);
+	let neighbors = match (dir, pdir) {
+		(Some(dir), Some(pdir)) => {
+			if dir == pdir {
+				~[dir]
+			} else {
+				~[dir, pdir]
+			}
+		},
+		(Some(dir), None) => {
+			if main_dir == dir {
+				~[dir, dir.turn(LEFT), dir.turn(RIGHT)]
+			} else {
+				~[dir, main_dir]
+			}
+		},
+		_ => {
+			~[main_dir, main_dir.turn(LEFT), main_dir.turn(RIGHT)]
+		}
+	};
+
+	if map.at(pos).can_see_through() {
+		for &d in neighbors.iter() {
+			let n = pos.neighbor(d);
+			match dir {
+				Some(_) => {
+					do_view(map, visible, n, d, Some(d), dir, depth - 1);
+				},
+				None => {
+					do_view(map, visible, n, main_dir, Some(d), dir, depth - 1);
+				}
+			};
+		}
 	}
 }
 
@@ -508,24 +1553,24 @@ impl Tile {
 }
 
 impl MapView for Map {
-	fn at(&mut self, pos: Position) -> Tile {
+	fn at(&self, pos: Position) -> Tile {
 		let p = self.wrap_position(pos);
 		self.tiles[p.x][p.y]
 	}
-	fn creature_at(&mut self, pos: Position) -> Option<@mut Creature> {
+	fn creature_at(&self, pos: Position) -> Option<CreatureId> {
 		let pos = self.wrap_position(pos);
-		self.creatures[pos.x][pos.y]
+		self.grid[pos.x][pos.y]
 	}
-	fn objects_at(&mut self, pos: Position) -> @mut ~[ ~Object ] {
+	fn objects_at<'a>(&'a self, pos: Position) -> &'a [ ~Object ] {
 		let pos = self.wrap_position(pos);
-		self.objects[pos.x][pos.y]
+		self.objects[pos.x][pos.y].as_slice()
 	}
 	fn translate(&self, pos : Position) -> Position {
 		pos
 	}
 }
 
-fn each_in_vrect<T: MapView>(s: &mut T, cp : Position, rx : int, ry : int, f : &fn(position : Position, t: Tile)) {
+fn each_in_vrect<T: MapView>(s: &T, cp : Position, rx : int, ry : int, f : &fn(position : Position, t: Tile)) {
 	for vx in range(-rx, rx + 1) {
 		for vy in range(-ry, ry + 1) {
 			let x = cp.x + vx;
@@ -540,41 +1585,160 @@ impl Map {
 	pub fn new() -> Map {
 		let mut rng = rand::rng();
 
-		let map = vec::from_fn(MAP_WIDTH, |_| {
+		let tiles = vec::from_fn(MAP_WIDTH, |_| {
 			vec::from_fn(MAP_HEIGHT, |_| {
-				if (rng.gen_int_range(0, 3) == 0) {
-					WALL
-				} else {
-					FLOOR
+				match rng.gen_int_range(0, 12) {
+					0..3 => WALL,
+					4 => SWAMP,
+					_ => FLOOR
 				}
 			})
 		});
+		Map::with_tiles(tiles)
+	}
 
-		let creatures = vec::from_fn(MAP_WIDTH, |_| {
-			vec::from_fn(MAP_HEIGHT, |_| {
+	// Empty map of given tiles, indexed `tiles[x][y]`
+	pub fn with_tiles(tiles : ~[~[Tile]]) -> Map {
+		let width = tiles.len();
+		let height = tiles[0].len();
+
+		let grid = vec::from_fn(width, |_| {
+			vec::from_fn(height, |_| {
 				None
 			})
 		});
 
-		let objects = vec::from_fn(MAP_WIDTH, |_| {
-			vec::from_fn(MAP_HEIGHT, |_| {
-				@mut ~[]
+		let objects = vec::from_fn(width, |_| {
+			vec::from_fn(height, |_| {
+				~[]
 			})
 		});
 		Map {
-			tiles: map, creatures: creatures,
-			width: MAP_WIDTH, height: MAP_HEIGHT,
-			objects: objects
+			tiles: tiles, creatures: ~[], grid: grid,
+			width: width, height: height,
+			objects: objects,
+			projectiles: ~[],
+			events: ~[],
+			queue: PriorityQueue::new(),
+			clock: Clock::new()
 		}
 	}
 
-	fn wrap_position(&self, pos : Position) -> Position {
+	pub fn now(&self) -> uint {
+		self.clock.ticks
+	}
+
+	pub fn creature<'a>(&'a self, id : CreatureId) -> &'a Creature {
+		&self.creatures[id]
+	}
+
+	pub fn creature_mut<'a>(&'a mut self, id : CreatureId) -> &'a mut Creature {
+		&mut self.creatures[id]
+	}
+
+	fn objects_at_mut<'a>(&'a mut self, pos : Position) -> &'a mut ~[ ~Object ] {
+		let pos = self.wrap_position(pos);
+		&mut self.objects[pos.x][pos.y]
+	}
+
+	fn emit(&mut self, event : Event) {
+		self.events.push(event);
+	}
+
+	// Hand over everything that happened since last time
+	pub fn drain_events(&mut self) -> ~[ Event ] {
+		util::replace(&mut self.events, ~[])
+	}
+
+	pub fn schedule(&mut self, id : CreatureId, time : uint) {
+		self.creatures[id].wake_at = time;
+		self.queue.push(Wakeup { time: time, id: id });
+	}
+
+	/**
+	 * Advance time straight to the next creature that needs attention
+	 * and wake it up. Ticks when nobody has anything to do are skipped.
+	 * `decide` picks the next action for a creature that is ready.
+	 *
+	 * Returns woken creature and whether it did something visible,
+	 * or None if there's nobody left to wake up.
+	 *
+	 * Events piling up on the way are kept until `drain_events` is
+	 * called, so callers have to drain them every now and then even
+	 * if they don't care about them (`game::Game::step` does).
+	 */
+	pub fn step(&mut self, decide : &fn(&mut Map, CreatureId) -> Action)
+			-> Option<(CreatureId, bool)> {
+		while !self.queue.is_empty() {
+			let w = self.queue.pop();
+
+			// creature was rescheduled or died since
+			if w.time != self.creatures[w.id].wake_at || !self.creatures[w.id].alive() {
+				loop;
+			}
+
+			self.clock.ticks = w.time;
+			let now = self.now();
+			self.projectiles.retain(|p| p.until > now);
+
+			return Some((w.id, Creature::wake(self, w.id, decide)));
+		}
+		None
+	}
+
+	// When `step` will wake the next creature up
+	pub fn next_wakeup(&mut self) -> Option<uint> {
+		while !self.queue.is_empty() {
+			let w = *self.queue.top();
+			if w.time == self.creatures[w.id].wake_at && self.creatures[w.id].alive() {
+				return Some(w.time);
+			}
+			self.queue.pop();
+		}
+		None
+	}
+
+	pub fn projectile_at(&self, pos : Position) -> bool {
+		let pos = self.wrap_position(pos);
+		do self.projectiles.iter().any |p| {
+			p.until > self.now() && p.path.iter().any(|&pp| self.wrap_position(pp) == pos)
+		}
+	}
+
+	pub fn wrap_position(&self, pos : Position) -> Position {
 		Position {
 			x: modulo(pos.x, self.width as int),
 			y: modulo(pos.y, self.height as int)
 		}
 	}
 
+	// `to` moved by whole map sizes to where it's closest to `from`,
+	// so plain hex math works across the edges of the map
+	pub fn closest(&self, from : Position, to : Position) -> Position {
+		let f = self.wrap_position(from);
+		let t = self.wrap_position(to);
+		let (w, h) = (self.width as int, self.height as int);
+		let mut best = t;
+		for &dx in [-w, 0, w].iter() {
+			for &dy in [-h, 0, h].iter() {
+				let p = Position { x: t.x + dx, y: t.y + dy };
+				if f.distance(p) < f.distance(best) {
+					best = p;
+				}
+			}
+		}
+		from + (best - f)
+	}
+
+	// Distance the short way around the map
+	pub fn distance(&self, from : Position, to : Position) -> int {
+		from.distance(self.closest(from, to))
+	}
+
+	pub fn direction_to(&self, from : Position, to : Position) -> Direction {
+		from.direction_to(self.closest(from, to))
+	}
+
 	fn for_each_tile(&mut self, f : &fn(Position, &mut Tile)) {
 		for x in range(0, self.width as int) {
 			for y in range(0, self.height as int) {
@@ -583,34 +1747,116 @@ impl Map {
 		}
 	}
 
-	fn for_each_creature(&mut self, f : &fn(@mut Creature)) {
-		for x in range(0, self.width as int) {
-			for y in range(0, self.height as int) {
-				match (self.creatures[x][y]) {
-					Some(creature) => f(creature),
+	// Spread noise through passable hexes, getting quieter with every step
+	// until it dies out `noise.loudness` hexes away from its source
+	pub fn emit_noise(&mut self, noise : Noise) {
+		let mut visited = vec::from_elem(self.width, vec::from_elem(self.height, false));
+		let start = self.wrap_position(noise.pos);
+		// with how many more hexes the noise can travel
+		let mut queue = ~[(start, noise.loudness)];
+		let mut i = 0;
+
+		visited[start.x][start.y] = true;
+		while i < queue.len() {
+			let (pos, left) = queue[i];
+			i += 1;
+
+			if pos != start {
+				match self.grid[pos.x][pos.y] {
+					Some(id) => self.creatures[id].hear(Noise {
+						pos: noise.pos, loudness: left + 1, faction: noise.faction
+					}),
 					None => {}
 				}
 			}
+
+			if left <= 0 {
+				loop;
+			}
+
+			for d in range(0, 6) {
+				let n = self.wrap_position(pos.neighbor(N.turn_by_int(d)));
+				if !visited[n.x][n.y] && self.at(n).is_passable() {
+					visited[n.x][n.y] = true;
+					queue.push((n, left - 1));
+				}
+			}
+		}
+	}
+
+	// First step of the shortest path from `from` to `to` through free hexes
+	pub fn path_step(&self, from : Position, to : Position, max : uint) -> Option<Direction> {
+		let start = self.wrap_position(from);
+		let goal = self.wrap_position(to);
+		let mut first_step = vec::from_fn(self.width, |_| {
+			vec::from_fn(self.height, |_| None)
+		});
+		let mut visited = vec::from_elem(self.width, vec::from_elem(self.height, false));
+		let mut queue = ~[(start, 0u)];
+		let mut i = 0;
+
+		visited[start.x][start.y] = true;
+		while i < queue.len() {
+			let (pos, depth) = queue[i];
+			i += 1;
+
+			if depth >= max {
+				loop;
+			}
+
+			for d in range(0, 6) {
+				let dir = N.turn_by_int(d);
+				let n = self.wrap_position(pos.neighbor(dir));
+				if visited[n.x][n.y] {
+					loop;
+				}
+				let step = if pos == start { Some(dir) } else { first_step[pos.x][pos.y] };
+				if n == goal {
+					return step;
+				}
+				if !self.at(n).is_passable() || self.grid[n.x][n.y].is_some() {
+					loop;
+				}
+				visited[n.x][n.y] = true;
+				first_step[n.x][n.y] = step;
+				queue.push((n, depth + 1));
+			}
+		}
+		None
+	}
+
+	// Living creatures only
+	pub fn for_each_creature(&self, f : &fn(&Creature)) {
+		for cr in self.creatures.iter() {
+			if cr.alive() {
+				f(cr);
+			}
 		}
 	}
 
-	fn spawn_creature<T:MoveController + 'static>(@mut self, pos : Position, dir : Direction,
-			controller : @mut T, player : bool
-			) -> Option<@mut Creature> {
+	// None if the hex is a wall or already taken
+	pub fn spawn_creature(&mut self, pos : Position, dir : Direction,
+			player : bool, faction : Faction) -> Option<CreatureId> {
+		let pos = self.wrap_position(pos);
 		if (!self.at(pos).is_passable()) {
 			return None;
 		}
-		match (self.creatures[pos.x][pos.y]) {
+		match (self.grid[pos.x][pos.y]) {
 			Some(_) => None,
 			None => {
-				let c = @mut Creature::new(self, pos, dir, controller, player);
-				self.creatures[pos.x][pos.y] = Some(c);
-				Some(c)
+				let id = self.creatures.len();
+				let c = Creature::new(self, id, pos, dir, player, faction);
+				let now = self.now();
+				self.creatures.push(c);
+				self.grid[pos.x][pos.y] = Some(id);
+				self.schedule(id, now);
+				self.emit(SPAWNED(id));
+				Some(id)
 			}
 		}
 	}
 
-	pub fn spawn_object(@mut self, pos : Position, obj : ~Object) {
+	pub fn spawn_object(&mut self, pos : Position, obj : ~Object) {
 		if (!self.at(pos).is_passable()) {
 			return;
 		}
@@ -626,9 +1872,7 @@ impl Map {
 		}
 	}
 
-	pub fn spawn_random_creature<T:MoveController + 'static>(
-			@mut self, controller : @mut T, player : bool
-			) -> @mut Creature {
+	pub fn spawn_random_creature(&mut self, player : bool, faction : Faction) -> CreatureId {
 
 		let mut rng = rand::rng();
 
@@ -636,56 +1880,69 @@ impl Map {
 
 		let dir = N.turn_by_int(rng.gen_int_range(0, 6));
 
-		match (self.spawn_creature(pos, dir, controller, player)) {
-			None => self.spawn_random_creature(controller, player),
-			Some(creature) => creature
+		match (self.spawn_creature(pos, dir, player, faction)) {
+			None => self.spawn_random_creature(player, faction),
+			Some(id) => id
 		}
 	}
 
-	fn move_creature(&mut self, cr : @mut Creature, pos : Position) {
+	fn move_creature(&mut self, id : CreatureId, pos : Position) {
 		let pos = &self.wrap_position(pos);
-		match (self.creatures[pos.x][pos.y]) {
+		match (self.grid[pos.x][pos.y]) {
 			Some(_) => {},
 			None => {
-				self.creatures[cr.pos.x][cr.pos.y] = None;
-				cr.pos = *pos;
-				self.creatures[pos.x][pos.y] = Some(cr);
+				let old = self.creatures[id].pos;
+				self.grid[old.x][old.y] = None;
+				self.creatures[id].pos = *pos;
+				self.grid[pos.x][pos.y] = Some(id);
 			}
 		}
 	}
 
-	fn remove_creature(&mut self, cr : @mut Creature) {
-		let pos = cr.pos;
+	fn remove_creature(&mut self, id : CreatureId) {
+		let pos = self.creatures[id].pos;
 		let pos = self.wrap_position(pos);
-		self.creatures[pos.x][pos.y] = None;
+		self.grid[pos.x][pos.y] = None;
+	}
+
+	// Take a dead creature off the map and out of the schedule
+	pub fn despawn(&mut self, id : CreatureId) {
+		self.remove_creature(id);
+		self.creatures[id].wake_at = NEVER;
+		if self.creatures[id].leaves_corpse {
+			let pos = self.creatures[id].pos;
+			let species = self.creatures[id].species;
+			self.spawn_object(pos, ~Corpse::new(species) as ~Object);
+		}
+		self.emit(DESPAWNED(id));
 	}
 }
 
-impl RelativeMap {
-	pub fn new(map: @mut Map, pos : Position, dir : Direction) -> RelativeMap {
+impl<'self> RelativeMap<'self> {
+	pub fn new(map: &'self Map, pos : Position, dir : Direction) -> RelativeMap<'self> {
 		RelativeMap{ map: map, pos: pos, dir: dir }
 	}
 
 	// Underlying map.
-	pub fn base(&mut self) -> @mut Map {
+	pub fn base(&self) -> &'self Map {
 		self.map
 	}
 }
 
-impl MapView for RelativeMap {
-	fn at(&mut self, pos: Position) -> Tile {
+impl<'self> MapView for RelativeMap<'self> {
+	fn at(&self, pos: Position) -> Tile {
 		let pos = self.translate(pos);
 		self.map.at(pos)
 	}
 
-	fn creature_at(&mut self, pos: Position) -> Option<@mut Creature> {
+	fn creature_at(&self, pos: Position) -> Option<CreatureId> {
 		let pos = self.translate(pos);
 		self.map.creature_at(pos)
 	}
 
-	fn objects_at(&mut self, pos: Position) -> @mut ~[ ~Object ] {
+	fn objects_at<'a>(&'a self, pos: Position) -> &'a [ ~Object ] {
 		let pos = self.translate(pos);
-		self.map.objects[pos.x][pos.y]
+		self.map.objects_at(pos)
 	}
 	fn translate(&self, pos : Position) -> Position {
 		match self.dir {
@@ -716,3 +1973,82 @@ impl MapView for RelativeMap {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use std::vec;
+
+	use super::{Map, Creature, Position, StatusEffect, FLOOR, N, S, FORWARD, POISON, BLOCK,
+		WAIT, GOBLINS, HUMANS};
+
+	#[test]
+	fn poison_does_not_kill() {
+		// the victim may dodge, so bite until it gets through
+		let mut i = 0;
+		loop {
+			let mut m = Map::with_tiles(vec::from_elem(8, vec::from_elem(8, FLOOR)));
+			let goblin = m.spawn_creature(Position { x: 3, y: 3 }, N, false, GOBLINS).unwrap();
+			let victim = m.spawn_creature(Position { x: 3, y: 2 }, S, false, HUMANS).unwrap();
+			{
+				let cr = m.creature_mut(goblin);
+				cr.attack = 100;
+				cr.venom = Some(StatusEffect::new(POISON, 1, 30));
+			}
+
+			Creature::melee(&mut m, goblin, FORWARD);
+			if m.creature(victim).life < m.creature(victim).max_life {
+				m.clock.ticks += 50;
+				Creature::catch_up(&mut m, victim);
+				assert!(m.creature(victim).alive());
+				assert_eq!(m.creature(victim).life, 1);
+				assert!(!m.creature(victim).has_effect(POISON));
+				return;
+			}
+
+			i += 1;
+			assert!(i < 100);
+		}
+	}
+
+	#[test]
+	fn poison_acts_on_a_busy_creature() {
+		let mut m = Map::with_tiles(vec::from_elem(8, vec::from_elem(8, FLOOR)));
+		let id = m.spawn_creature(Position { x: 3, y: 3 }, N, false, HUMANS).unwrap();
+		{
+			let cr = m.creature_mut(id);
+			// blocking isn't spoilt by getting hurt
+			cr.action = Some(BLOCK);
+			cr.action_at = 100;
+		}
+		Creature::apply_effect(&mut m, id, StatusEffect::new(POISON, 1, 20));
+		assert_eq!(m.creature(id).next_wake(), 1);
+
+		m.step(|_, _| WAIT);
+		assert_eq!(m.now(), 1);
+		assert_eq!(m.creature(id).life, 2);
+		assert_eq!(m.creature(id).next_wake(), 11);
+	}
+
+	#[test]
+	fn hex_lines() {
+		let p = Position { x: 0, y: 0 };
+		let line = p.line_to(Position { x: 3, y: 3 });
+		assert_eq!(line, ~[Position { x: 1, y: 1 }, Position { x: 2, y: 2 }, Position { x: 3, y: 3 }]);
+		let line = p.line_to(Position { x: 0, y: -2 });
+		assert_eq!(line, ~[Position { x: 0, y: -1 }, Position { x: 0, y: -2 }]);
+		assert!(p.line_to(p).is_empty());
+	}
+
+	#[test]
+	fn fires_at_a_target_across_the_seam() {
+		let mut m = Map::with_tiles(vec::from_elem(8, vec::from_elem(8, FLOOR)));
+		let shooter = m.spawn_creature(Position { x: 2, y: 1 }, N, false, HUMANS).unwrap();
+		// facing away, so it can't dodge
+		let target = m.spawn_creature(Position { x: 2, y: 6 }, N, false, GOBLINS).unwrap();
+
+		Creature::fire_at(&mut m, shooter, Position { x: 2, y: 6 });
+		assert!(m.projectile_at(Position { x: 2, y: 7 }));
+		assert!(!m.projectile_at(Position { x: 2, y: 2 }));
+		assert_eq!(m.creature(target).life, 1);
+	}
+}
@@ -0,0 +1,133 @@
+use std::cmp;
+
+use map;
+
+/**
+ * Something that happened, as told to the player
+ */
+pub struct Message {
+	time : map::Clock,
+	text : ~str
+}
+
+/**
+ * Everything the player took part in, oldest first
+ */
+pub struct MessageLog {
+	messages : ~[Message]
+}
+
+impl MessageLog {
+	pub fn new() -> MessageLog {
+		MessageLog { messages: ~[] }
+	}
+
+	pub fn add(&mut self, time : map::Clock, text : ~str) {
+		self.messages.push(Message { time: time, text: text });
+	}
+
+	pub fn len(&self) -> uint {
+		self.messages.len()
+	}
+
+	// Up to `n` messages, ending `back` messages before the newest one
+	pub fn last<'a>(&'a self, n : uint, back : uint) -> &'a [Message] {
+		let end = self.messages.len() - cmp::min(back, self.messages.len());
+		let start = end - cmp::min(n, end);
+		self.messages.slice(start, end)
+	}
+
+	// How far back a panel of `shown` messages, `back` messages back,
+	// ends up after scrolling `lines` further back
+	pub fn scroll(&self, back : uint, lines : int, shown : uint) -> uint {
+		let max = self.messages.len() - cmp::min(shown, self.messages.len());
+		cmp::min(cmp::max(back as int + lines, 0) as uint, max)
+	}
+}
+
+impl Message {
+	// As in "[0:12.4] The orc hits you for 1"
+	pub fn to_str(&self) -> ~str {
+		fmt!("[%s] %s", self.time.to_str(), self.text)
+	}
+}
+
+// "You" or "The orc", for the start of a sentence
+fn subject(cr : &map::Creature) -> ~str {
+	if cr.is_player() {
+		~"You"
+	} else {
+		fmt!("The %s", cr.species.name())
+	}
+}
+
+fn object(cr : &map::Creature) -> ~str {
+	if cr.is_player() {
+		~"you"
+	} else {
+		fmt!("the %s", cr.species.name())
+	}
+}
+
+// Verb agreeing with the subject, as in "you hit" or "the orc hits"
+fn verb(cr : &map::Creature, verb : &str) -> ~str {
+	if cr.is_player() {
+		verb.to_owned()
+	} else if verb.ends_with("s") {
+		fmt!("%ses", verb)
+	} else {
+		fmt!("%ss", verb)
+	}
+}
+
+impl map::Listener for MessageLog {
+	fn on_event(&mut self, map : &map::Map, event : &map::Event) {
+		let text = match *event {
+			map::HIT(Some(by), id, damage) => {
+				let a = map.creature(by);
+				let t = map.creature(id);
+				if !a.is_player() && !t.is_player() {
+					return;
+				}
+				fmt!("%s %s %s for %d", subject(a), verb(a, "hit"), object(t), damage)
+			},
+			map::HIT(None, id, damage) => {
+				if !map.creature(id).is_player() {
+					return;
+				}
+				fmt!("You suffer %d damage", damage)
+			},
+			map::MISSED(by, id) => {
+				let a = map.creature(by);
+				let t = map.creature(id);
+				if !a.is_player() && !t.is_player() {
+					return;
+				}
+				fmt!("%s %s %s", subject(a), verb(a, "miss"), object(t))
+			},
+			map::DIED(id, killer) => {
+				let v = map.creature(id);
+				match killer {
+					Some(k) if map.creature(k).is_player() || v.is_player() => {
+						let k = map.creature(k);
+						fmt!("%s %s %s", subject(k), verb(k, "kill"), object(v))
+					},
+					_ if v.is_player() => ~"You die",
+					_ => return
+				}
+			},
+			map::USED(id, obj) => {
+				if !map.creature(id).is_player() {
+					return;
+				}
+				match obj {
+					map::MEDKIT => ~"You feel better",
+					map::HERB => ~"You chew the herb and feel warmth spreading",
+					map::CORPSE => return
+				}
+			},
+			map::SPAWNED(_) | map::DESPAWNED(_) => return
+		};
+		self.add(map.clock, text);
+	}
+}
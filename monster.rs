@@ -0,0 +1,104 @@
+use map;
+use map::MapView;
+use bt;
+
+/**
+ * Simple brain: fights what is in front, checks out noises
+ * and hostiles it spots, and runs when its morale breaks
+ */
+pub struct MonsterController {
+	fleeing : bool,
+	investigating : Option<map::Position>
+}
+
+impl MonsterController {
+	pub fn new() -> MonsterController {
+		MonsterController { fleeing: false, investigating: None }
+	}
+
+	// Go check what made the noise
+	fn investigate(&mut self, map : &map::Map, id : map::CreatureId, target : map::Position) -> map::Action {
+		let cr = map.creature(id);
+		let pos = cr.pos;
+		let cd = cr.dir;
+
+		let towards = map.direction_to(pos, target);
+		if map.distance(pos, target) <= 1 {
+			self.investigating = None;
+			return map::TURN(cd.turn_towards(towards));
+		}
+
+		if cd != towards {
+			map::TURN(cd.turn_towards(towards))
+		} else if map.at(pos.neighbor(cd)).is_passable() {
+			map::MOVE(map::FORWARD)
+		} else {
+			self.investigating = None;
+			map::TURN(map::LEFT)
+		}
+	}
+}
+
+impl map::MoveController for MonsterController {
+	fn get_move(&mut self, map : &mut map::Map, id : map::CreatureId) -> map::Action {
+		let morale = map.creature(id).morale;
+		if self.fleeing {
+			self.fleeing = morale < map::MORALE_RECOVERED;
+		} else {
+			self.fleeing = morale < map::MORALE_BROKEN;
+		}
+
+		if self.fleeing {
+			match map.creature(id).threat {
+				Some(threat) => {
+					return bt::flee_from(map, id, threat);
+				},
+				None => {}
+			}
+		}
+
+		let dirs = [map::FORWARD, map::LEFT, map::RIGHT];
+
+		for &dir in dirs.iter() {
+			let cr = map.creature(id);
+			let pos = cr.pos.neighbor(cr.dir.turn(dir));
+			match map.creature_at(pos) {
+				None => {}
+				Some(c) => {
+					if (cr.is_hostile_to(map.creature(c))) {
+						return map::MELEE(dir);
+					} else {
+						return map::TURN(map::LEFT)
+					}
+				}
+			}
+		};
+
+		match map::Creature::nearest_visible_hostile(map, id) {
+			Some(enemy) => {
+				self.investigating = Some(map.creature(enemy).pos);
+			},
+			None => {}
+		}
+
+		let heard = map.creature(id).heard;
+		match heard {
+			Some(noise) => {
+				if map.creature(id).faction.attitude_to(noise.faction) == map::HOSTILE {
+					self.investigating = Some(noise.pos);
+				}
+				map.creature_mut(id).heard = None;
+			},
+			None => {}
+		}
+
+		match self.investigating {
+			Some(target) => {
+				return self.investigate(map, id, target);
+			},
+			None => {}
+		}
+
+		bt::wander(map, id)
+	}
+}
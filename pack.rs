@@ -0,0 +1,192 @@
+use map;
+use map::MapView;
+use bt;
+
+/**
+ * Knowledge shared by all members of a pack
+ *
+ * Members report where they've seen the player and split the hexes
+ * around the player among themselves, so they close in from all sides
+ * instead of queueing up in a corridor.
+ */
+pub struct Pack {
+	members : ~[map::CreatureId],
+	target : Option<map::Position>,
+	target_dir : map::Direction
+}
+
+pub struct PackController {
+	pack : @mut Pack
+}
+
+// Neighbors of the target, most wanted first: back, back flanks,
+// front flanks and front as a last resort
+static SLOT_PREFERENCE: [int, ..6] = [3, 2, 4, 1, 5, 0];
+
+// Longest path (in hexes) members will walk toward their slots
+static PATH_LIMIT: uint = 16;
+
+impl Pack {
+	pub fn new() -> Pack {
+		Pack { members: ~[], target: None, target_dir: map::N }
+	}
+
+	pub fn join(&mut self, id : map::CreatureId) {
+		self.members.push(id);
+	}
+
+	fn leave(&mut self, id : map::CreatureId) {
+		self.members.retain(|&m| m != id);
+	}
+
+	fn spot(&mut self, pos : map::Position, dir : map::Direction) {
+		self.target = Some(pos);
+		self.target_dir = dir;
+	}
+
+	fn lose_target(&mut self) {
+		self.target = None;
+	}
+
+	// Hex around the target assigned to `id`, if there's one left for it
+	fn slot_for(&self, map : &map::Map, id : map::CreatureId) -> Option<map::Position> {
+		let target = match self.target {
+			Some(t) => t,
+			None => return None
+		};
+
+		let mut taken = ~[];
+		for &rel in SLOT_PREFERENCE.iter() {
+			let slot = target.neighbor(self.target_dir.turn_by_int(rel));
+			if !map.at(slot).is_passable() {
+				loop;
+			}
+
+			let mut nearest : Option<map::CreatureId> = None;
+			for &m in self.members.iter() {
+				if taken.contains(&m) {
+					loop;
+				}
+				let dist = map.distance(map.creature(m).pos, slot);
+				nearest = match nearest {
+					Some(n) if map.distance(map.creature(n).pos, slot) <= dist => Some(n),
+					_ => Some(m)
+				};
+			}
+
+			match nearest {
+				Some(n) => {
+					if n == id {
+						return Some(slot);
+					}
+					taken.push(n);
+				},
+				None => return None
+			}
+		}
+		None
+	}
+}
+
+// Dead members don't get slots
+impl map::Listener for Pack {
+	fn on_event(&mut self, _ : &map::Map, event : &map::Event) {
+		match *event {
+			map::DESPAWNED(id) => self.leave(id),
+			_ => {}
+		}
+	}
+}
+
+impl PackController {
+	pub fn new(pack : @mut Pack) -> PackController {
+		PackController { pack: pack }
+	}
+
+	// Tell the pack what this member sees
+	fn look(&mut self, map : &mut map::Map, id : map::CreatureId) {
+		map::Creature::refresh_visibility(map, id);
+
+		let cr = map.creature(id);
+		let mut seen = None;
+		do map.for_each_creature |c| {
+			if c.is_player() && cr.sees(c.pos) {
+				seen = Some((c.pos, c.dir));
+			}
+		}
+
+		match seen {
+			Some((pos, dir)) => self.pack.spot(pos, dir),
+			None => {
+				match self.pack.target {
+					Some(t) if cr.sees(t) => self.pack.lose_target(),
+					_ => {}
+				}
+			}
+		}
+	}
+}
+
+impl map::MoveController for PackController {
+	fn get_move(&mut self, map : &mut map::Map, id : map::CreatureId) -> map::Action {
+		self.look(map, id);
+
+		let target = match self.pack.target {
+			Some(t) => t,
+			None => return bt::wander(map, id)
+		};
+
+		let cr = map.creature(id);
+		let pos = cr.pos;
+		let cd = cr.dir;
+		let towards = map.direction_to(pos, target);
+
+		if map.distance(pos, target) == 1 && cd == towards {
+			return map::MELEE(map::FORWARD);
+		}
+
+		let dest = match self.pack.slot_for(map, id) {
+			Some(slot) => slot,
+			None => target
+		};
+
+		if map.wrap_position(dest) == pos {
+			return map::TURN(cd.turn_towards(towards));
+		}
+
+		match map.path_step(pos, dest, PATH_LIMIT) {
+			Some(d) => bt::step(cr, d),
+			None => map::TURN(cd.turn_towards(towards))
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::vec;
+
+	use map;
+	use map::MoveController;
+	use super::{Pack, PackController};
+
+	#[test]
+	fn attacks_player_across_the_seam() {
+		let tiles = vec::from_elem(32, vec::from_elem(32, map::FLOOR));
+		let mut m = map::Map::with_tiles(tiles);
+		let goblin_pos = map::Position { x: 31, y: 5 };
+		let player_pos = map::Position { x: 0, y: 5 };
+		m.spawn_creature(player_pos, map::N, true, map::HUMANS).unwrap();
+		let goblin = m.spawn_creature(goblin_pos, map::NE, false, map::GOBLINS).unwrap();
+
+		assert_eq!(m.distance(goblin_pos, player_pos), 1);
+		assert_eq!(m.direction_to(goblin_pos, player_pos), map::NE);
+
+		let pack = @mut Pack::new();
+		pack.join(goblin);
+		let mut ctrl = PackController::new(pack);
+		match ctrl.get_move(&mut m, goblin) {
+			map::MELEE(map::FORWARD) => {},
+			a => fail!(fmt!("goblin chose to %s", a.to_str()))
+		}
+	}
+}
@@ -0,0 +1,23 @@
+/*!
+ * Game rules of rustyhex, without any frontend
+ *
+ * Everything needed to run the simulation: the map, creatures and
+ * their actions, stock creature brains and a message log. Frontends,
+ * tools and bots link against this and drive it through `Map::step`.
+ */
+
+#[link(name = "rustyhex", vers = "0.1")];
+#[crate_type = "lib"];
+
+extern mod extra;
+
+pub use map::{Map, Creature, CreatureId, Action, MoveController, MapView, Position};
+
+pub mod map;
+pub mod bt;
+pub mod pack;
+pub mod messages;
+pub mod monster;
+pub mod game;
+pub mod input;
+pub mod snapshot;
@@ -0,0 +1,142 @@
+/*!
+ * Plain text pictures of the map
+ *
+ * The whole map is drawn north up, one line per half hex row, with
+ * every other column shifted half a hex down like on the screen.
+ * Output only depends on the map, so it can be compared against in
+ * tests and pasted into bug reports.
+ *
+ * Seen hexes: `.` floor, `#` wall, `~` swamp, `*` projectile,
+ * `+` medkit, `"` herb, `%` corpse, `@` the viewer and the first
+ * letter of the species for other creatures. Remembered hexes:
+ * `,` floor, `X` wall, `-` swamp. Unknown hexes are blank.
+ */
+
+use map;
+use map::MapView;
+
+fn tile_glyph(tile : map::Tile, visible : bool) -> char {
+	match (tile, visible) {
+		(map::FLOOR, true) => '.',
+		(map::WALL, true) => '#',
+		(map::SWAMP, true) => '~',
+		(map::FLOOR, false) => ',',
+		(map::WALL, false) => 'X',
+		(map::SWAMP, false) => '-'
+	}
+}
+
+fn object_glyph(t : map::ObjectType) -> char {
+	match t {
+		map::MEDKIT => '+',
+		map::HERB => '"',
+		map::CORPSE => '%'
+	}
+}
+
+fn hex_glyph(map : &map::Map, viewer : Option<&map::Creature>, pos : map::Position) -> char {
+	let (known, visible) = match viewer {
+		Some(v) => (v.knows(pos), v.sees(pos)),
+		None => (true, true)
+	};
+	if !known {
+		return ' ';
+	}
+	if !visible {
+		return tile_glyph(map.at(pos), false);
+	}
+
+	match map.creature_at(pos) {
+		Some(id) => {
+			let same = match viewer {
+				Some(v) => v.id == id,
+				None => false
+			};
+			return if same { '@' } else { map.creature(id).species.name()[0] as char };
+		},
+		None => {}
+	}
+	if map.projectile_at(pos) {
+		return '*';
+	}
+	let objs = map.objects_at(pos);
+	if objs.len() > 0 {
+		return object_glyph(objs[objs.len() - 1].get_type());
+	}
+	tile_glyph(map.at(pos), true)
+}
+
+/**
+ * The map as seen by `viewer`, or all of it when there's no viewer
+ *
+ * Lines have no trailing spaces and each ends with a newline.
+ */
+pub fn render(map : &map::Map, viewer : Option<map::CreatureId>) -> ~str {
+	let viewer = viewer.map(|&id| map.creature(id));
+	let rows = 2 * map.height;
+
+	let mut lines = ~[];
+	for row in range(0, rows) {
+		let mut line = ~"";
+		for x in range(0, map.width) {
+			// hex (x, y) sits on row 2y - x, wrapped around
+			if (row + x) % 2 != 0 {
+				line.push_str("  ");
+				loop;
+			}
+			let y = ((row + x) / 2) % map.height;
+			let pos = map::Position { x: x as int, y: y as int };
+			line.push_char(hex_glyph(map, viewer, pos));
+			line.push_char(' ');
+		}
+		lines.push(line.trim_right().to_owned());
+	}
+
+	let mut out = ~"";
+	for line in lines.iter() {
+		out.push_str(*line);
+		out.push_char('\n');
+	}
+	out
+}
+
+#[cfg(test)]
+mod test {
+	use std::vec;
+
+	use map;
+	use super::render;
+
+	#[test]
+	fn whole_map() {
+		// tiles[x][y]
+		let tiles = ~[~[map::FLOOR, map::SWAMP], ~[map::WALL, map::FLOOR]];
+		let mut m = map::Map::with_tiles(tiles);
+		let id = m.spawn_creature(map::Position { x: 1, y: 1 }, map::N, false, map::ANIMALS).unwrap();
+		m.creature_mut(id).species = map::WOLF;
+		m.spawn_object(map::Position { x: 0, y: 0 }, ~map::Medkit::new() as ~map::Object);
+
+		// (1, 0) is on row -1, so it wraps around to the bottom
+		assert_eq!(render(&m, None), ~"+\n  w\n~\n  #\n");
+	}
+
+	#[test]
+	fn seen_remembered_and_unknown() {
+		let mut tiles = vec::from_elem(4, vec::from_elem(3, map::FLOOR));
+		for &(x, y) in [(0u, 0u), (1, 0), (2, 1), (2, 2)].iter() {
+			tiles[x][y] = map::WALL;
+		}
+		let mut m = map::Map::with_tiles(tiles);
+		let id = m.spawn_creature(map::Position { x: 1, y: 1 }, map::N, true, map::HUMANS).unwrap();
+
+		// walls to the north, northwest and northeast block the view
+		map::Creature::refresh_visibility(&mut m, id);
+		assert_eq!(render(&m, Some(id)), ~"#   #\n  @\n\n\n\n  #\n");
+
+		// turning right brings the southeast wall into view, the
+		// northwest one is only remembered now
+		m.creature_mut(id).turn(map::RIGHT);
+		map::Creature::refresh_visibility(&mut m, id);
+		assert_eq!(render(&m, Some(id)), ~"X   #\n  @\n    #\n\n\n  #\n");
+	}
+}
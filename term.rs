@@ -0,0 +1,480 @@
+/*!
+ * Terminal frontend
+ *
+ * Draws the map with ANSI escape codes and reads keys from a raw
+ * terminal, so the game can be played over SSH or without SDL.
+ * Bindings are the same as in the SDL frontend: Shift is typed as an
+ * uppercase letter, Alt as Esc followed by the key and digits act as
+ * the numpad.
+ */
+
+extern mod rustyhex;
+
+use std::cmp;
+use std::io;
+use std::run;
+
+use rustyhex::map;
+use rustyhex::map::MapView;
+use rustyhex::game::Game;
+use rustyhex::input;
+use rustyhex::messages;
+
+use std::libc::{c_int, c_void, size_t, ssize_t};
+extern {
+	fn read(fd : c_int, buf : *mut c_void, count : size_t) -> ssize_t;
+	fn usleep(n : c_int) -> c_int;
+}
+
+// Hex columns on each side of the player
+static MAP_COLUMNS: int = 19;
+// Half hex rows, columns are staggered by one
+static MAP_ROWS: int = 17;
+static LOG_LINES: uint = 5;
+static SCREEN_COLUMNS: uint = 80;
+
+// Microseconds to wait for the rest of an escape sequence
+static ESCAPE_WAIT: int = 20000;
+// How often keys are looked for while waiting for the player
+static FRAME_DELAY: int = 10000;
+
+static REMEMBERED: &'static str = "90";
+
+pub struct Term {
+	player : Option<map::CreatureId>,
+	log : @mut messages::MessageLog,
+	// how many messages back the log is scrolled
+	log_scroll : uint,
+	controls : input::Controls,
+	// something on screen changed without game time passing
+	redraw : bool,
+}
+
+pub struct PlayerController {
+	term : @mut Term
+}
+
+#[fixed_stack_segment]
+fn sleep(us : int) {
+	unsafe {
+		usleep(us as c_int);
+	}
+}
+
+// Byte typed by the player, if there's one waiting
+#[fixed_stack_segment]
+fn read_byte() -> Option<u8> {
+	let mut b = 0u8;
+	let n = unsafe { read(0, &mut b as *mut u8 as *mut c_void, 1) };
+	if n == 1 { Some(b) } else { None }
+}
+
+fn plain_key(b : u8) -> Option<(input::Key, input::Modifiers)> {
+	let none = input::Modifiers::none();
+	let c = b as char;
+	match c {
+		// there's no SIGINT in raw mode
+		'\x03' => Some((input::ESCAPE, none)),
+		'0'..'9' => Some((input::KEYPAD(b as uint - '0' as uint), none)),
+		'A'..'Z' => {
+			Some((input::CHAR((b + 32) as char), input::Modifiers { shift: true, ..none }))
+		},
+		'\x01'..'\x1a' if c != '\t' && c != '\r' => {
+			Some((input::CHAR((b + 96) as char), input::Modifiers { ctrl: true, ..none }))
+		},
+		'!'..'~' => Some((input::CHAR(c), none)),
+		_ => None
+	}
+}
+
+// Rest of an "Esc [" sequence: arrows and page keys, with modifiers
+// sent as a second parameter
+fn read_csi() -> Option<(input::Key, input::Modifiers)> {
+	let mut params = ~"";
+	let mut last = 0u8;
+	loop {
+		match read_byte() {
+			Some(b) if b >= 0x40 && b <= 0x7e => {
+				last = b;
+				break;
+			},
+			Some(b) => params.push_char(b as char),
+			None => return None
+		}
+	}
+
+	let fields : ~[&str] = params.split_iter(';').collect();
+	let key = match last as char {
+		'A' => input::UP,
+		'B' => input::DOWN,
+		'C' => input::RIGHT,
+		'D' => input::LEFT,
+		'~' if fields[0] == "5" => input::PAGE_UP,
+		'~' if fields[0] == "6" => input::PAGE_DOWN,
+		_ => return None
+	};
+	let bits = if fields.len() > 1 {
+		match from_str::<uint>(fields[1]) {
+			Some(n) if n > 0 => n - 1,
+			_ => 0
+		}
+	} else {
+		0
+	};
+	Some((key, input::Modifiers {
+		shift: bits & 1 != 0,
+		alt: bits & 2 != 0,
+		ctrl: bits & 4 != 0
+	}))
+}
+
+fn read_key() -> Option<(input::Key, input::Modifiers)> {
+	let b = match read_byte() {
+		Some(b) => b,
+		None => return None
+	};
+	if b != 27 {
+		return plain_key(b);
+	}
+
+	sleep(ESCAPE_WAIT);
+	match read_byte() {
+		None => Some((input::ESCAPE, input::Modifiers::none())),
+		Some(91) => read_csi(),
+		// arrows in application cursor mode
+		Some(79) => match read_byte() {
+			Some(65) => Some((input::UP, input::Modifiers::none())),
+			Some(66) => Some((input::DOWN, input::Modifiers::none())),
+			Some(67) => Some((input::RIGHT, input::Modifiers::none())),
+			Some(68) => Some((input::LEFT, input::Modifiers::none())),
+			_ => None
+		},
+		Some(b) => do plain_key(b).map |&(key, mods)| {
+			(key, input::Modifiers { alt: true, ..mods })
+		}
+	}
+}
+
+// Keys reach the game one at a time, unechoed and without waiting
+fn raw_mode(on : bool) {
+	let args = if on {
+		~[~"raw", ~"-echo", ~"min", ~"0", ~"time", ~"0"]
+	} else {
+		~[~"sane"]
+	};
+	run::process_status("stty", args);
+}
+
+fn paint(color : &str, glyph : &str) -> ~str {
+	fmt!("\x1b[%sm%s\x1b[0m", color, glyph)
+}
+
+fn faction_color(faction : map::Faction) -> &'static str {
+	match faction {
+		map::ORCS => "31",
+		map::GOBLINS => "32",
+		map::ANIMALS => "33",
+		map::HUMANS => "34"
+	}
+}
+
+fn tile_glyph(tile : map::Tile) -> (&'static str, &'static str) {
+	match tile {
+		map::FLOOR => ("37", "."),
+		map::WALL => ("1;37", "#"),
+		map::SWAMP => ("36", "~")
+	}
+}
+
+fn object_glyph(t : map::ObjectType) -> (&'static str, &'static str) {
+	match t {
+		map::MEDKIT => ("1;31", "+"),
+		map::HERB => ("32", "\""),
+		map::CORPSE => ("33", "%")
+	}
+}
+
+// Arrow pointing where a creature faces, `d` relative to the view
+fn facing_glyph(d : map::Direction) -> &'static str {
+	match d {
+		map::N => "↑",
+		map::NE => "↗",
+		map::SE => "↘",
+		map::S => "↓",
+		map::SW => "↙",
+		map::NW => "↖"
+	}
+}
+
+// Plain text cut down to `width` characters
+fn clip(text : &str, width : uint) -> ~str {
+	text.slice_chars(0, cmp::min(text.char_len(), width)).to_owned()
+}
+
+impl Term {
+	pub fn new() -> Term {
+		let controls = match input::Controls::load(&Path(input::CONFIG_FILE)) {
+			Ok(controls) => controls,
+			Err(e) => fail!(e)
+		};
+		Term {
+			player: None,
+			log: @mut messages::MessageLog::new(),
+			log_scroll: 0,
+			controls: controls,
+			redraw: false
+		}
+	}
+
+	pub fn set_player(&mut self, id : map::CreatureId) {
+		self.player = Some(id);
+	}
+
+	fn creature_cell(&self, map : &map::Map, creature : &map::Creature,
+			view_dir : map::Direction) -> ~str {
+		let color = if creature.is_player() { "1;37" } else { faction_color(creature.faction) };
+		let color = if creature.last_hit_time(map.now()) < 8 {
+			fmt!("7;%s", color)
+		} else {
+			color.to_owned()
+		};
+		paint(color, facing_glyph(creature.dir.relative_to(view_dir)))
+	}
+
+	fn hex_cell(&self, map : &map::Map, player : &map::Creature,
+			rm : &map::RelativeMap, view_dir : map::Direction, pos : map::Position) -> ~str {
+		let tpos = rm.translate(pos);
+		let base = rm.base();
+		if player.alive() && !player.knows(tpos) {
+			return ~" ";
+		}
+		if player.alive() && !player.sees(tpos) {
+			let (_, glyph) = tile_glyph(base.at(tpos));
+			return paint(REMEMBERED, glyph);
+		}
+
+		match base.creature_at(tpos) {
+			Some(id) => return self.creature_cell(map, base.creature(id), view_dir),
+			None => {}
+		}
+		if base.projectile_at(tpos) {
+			return paint("1;33", "*");
+		}
+		let objs = base.objects_at(tpos);
+		let (color, glyph) = if objs.len() > 0 {
+			object_glyph(objs[objs.len() - 1].get_type())
+		} else {
+			tile_glyph(base.at(tpos))
+		};
+		paint(color, glyph)
+	}
+
+	fn hud_line(&self, map : &map::Map, player : &map::Creature) -> ~str {
+		let action = match player.action {
+			Some(a) if player.alive() => {
+				fmt!("%s (%u)", a.to_str(), player.pre_action_ticks(map.now()))
+			},
+			_ => ~"-"
+		};
+		let life = fmt!("Life %d/%d", cmp::max(player.life, 0), player.max_life);
+		let life = if player.life * 3 <= player.max_life { paint("31", life) } else { life };
+		let sneaking = if self.controls.sneaking { "  Sneaking" } else { "" };
+		fmt!("%s  Doing: %s  Kills %u  Turn %u (%s)%s",
+			life, action, player.kills, map.clock.turns, map.clock.to_str(), sneaking)
+	}
+
+	fn log_lines(&self) -> ~[~str] {
+		let mut lines = ~[];
+		for msg in self.log.last(LOG_LINES, self.log_scroll).iter() {
+			let stamp = fmt!("[%s] ", msg.time.to_str());
+			let text = clip(msg.text, SCREEN_COLUMNS - 1 - stamp.char_len());
+			lines.push(fmt!("%s%s", paint(REMEMBERED, stamp), text));
+		}
+		lines
+	}
+
+	pub fn draw(&mut self, map : &map::Map) {
+		self.redraw = false;
+
+		let player = match self.player {
+			Some(p) => map.creature(p),
+			None => {
+				return;
+			}
+		};
+
+		let view_dir = if self.controls.absolute { map::N } else { player.dir };
+		let rm = map::RelativeMap::new(map, player.pos, view_dir);
+		// the player sits near the bottom when the view turns with them
+		let top = if self.controls.absolute { -MAP_ROWS / 2 } else { 2 - MAP_ROWS };
+
+		let mut lines = ~[self.hud_line(map, player)];
+		for row in range(top, top + MAP_ROWS) {
+			let mut line = ~"";
+			for col in range(-MAP_COLUMNS, MAP_COLUMNS + 1) {
+				if (row + col) % 2 != 0 {
+					line.push_str("  ");
+					loop;
+				}
+				let pos = map::Position { x: col, y: (row + col) / 2 };
+				if pos.x == 0 && pos.y == 0 && player.alive() {
+					line.push_str(self.creature_cell(map, player, view_dir));
+				} else {
+					line.push_str(self.hex_cell(map, player, &rm, view_dir, pos));
+				}
+				line.push_char(' ');
+			}
+			lines.push(line);
+		}
+		lines.push_all_move(self.log_lines());
+
+		let mut frame = ~"\x1b[H";
+		for line in lines.iter() {
+			frame.push_str(*line);
+			frame.push_str("\x1b[K\r\n");
+		}
+		frame.push_str("\x1b[J");
+		io::stdout().write_str(frame);
+		io::stdout().flush();
+	}
+
+	// Positive `lines` go back to older messages
+	fn scroll_log(&mut self, lines : int) {
+		let scroll = self.log.scroll(self.log_scroll, lines, LOG_LINES);
+		if scroll != self.log_scroll {
+			self.log_scroll = scroll;
+			self.redraw = true;
+		}
+	}
+
+	// Bring the screen up to date with a command
+	fn show(&mut self, command : input::Command) {
+		match command {
+			input::SNEAK | input::ORIENTATION => self.redraw = true,
+			input::SCROLL(lines) => self.scroll_log(lines),
+			// no minimap in a terminal
+			_ => {}
+		}
+	}
+
+	fn key_to_action(&mut self, key : input::Key, mods : input::Modifiers,
+			map : &map::Map, player : map::CreatureId) -> Option<map::Action> {
+		match self.controls.press(key, mods, map, player, false) {
+			Some(input::ACT(a)) => Some(a),
+			Some(command) => {
+				self.show(command);
+				None
+			},
+			None => None
+		}
+	}
+
+	// Look for keys that matter while the player is not deciding,
+	// keeping the actions for when they are
+	pub fn poll_input(&mut self, map : &map::Map) {
+		let player = match self.player {
+			Some(p) => p,
+			None => return
+		};
+		let alive = map.creature(player).alive();
+		loop {
+			let (key, mods) = match read_key() {
+				Some(k) => k,
+				None => return
+			};
+			// the dead have nothing to type ahead for
+			match self.controls.press(key, mods, map, player, alive) {
+				Some(command) => self.show(command),
+				None => {}
+			}
+		}
+	}
+
+	pub fn get_input(&mut self, map : &map::Map) -> map::Action {
+		let player = self.player.unwrap();
+		match self.controls.typed_action(map, player) {
+			Some(a) => return a,
+			None => {}
+		}
+		loop {
+			match read_key() {
+				Some((key, mods)) => {
+					match self.key_to_action(key, mods, map, player) {
+						Some(a) => return a,
+						None => {}
+					}
+					if self.redraw {
+						self.draw(map);
+					}
+				},
+				None => sleep(FRAME_DELAY)
+			}
+		}
+	}
+}
+
+impl PlayerController {
+	fn new(term : @mut Term) -> PlayerController {
+		PlayerController { term: term }
+	}
+}
+
+impl map::MoveController for PlayerController {
+	fn get_move(&mut self, map : &mut map::Map, _ : map::CreatureId) -> map::Action {
+		self.term.draw(map);
+		self.term.get_input(map)
+	}
+}
+
+fn main() {
+	let term = @mut Term::new();
+
+	let mut game = Game::new();
+	game.subscribe(term.log as @mut map::Listener);
+	let player = game.spawn_player(@mut PlayerController::new(term) as @mut map::MoveController);
+	term.set_player(player);
+
+	raw_mode(true);
+	// clear the screen and hide the cursor
+	io::stdout().write_str("\x1b[2J\x1b[?25l");
+	term.draw(&game.map);
+
+	let mut now = game.map.now();
+	// game time that real time has caught up with
+	let mut clock = now;
+	loop {
+		let due = match game.map.next_wakeup() {
+			Some(time) => time <= clock,
+			None => true
+		};
+		if due {
+			game.step();
+			clock = cmp::max(clock, game.map.now());
+		} else {
+			sleep(1000000 / map::TICKS_PER_SECOND as int);
+			clock += 1;
+		}
+
+		if (term.controls.exit) {
+			break;
+		}
+
+		let busy = game.map.creature(player).pre_action_ticks(game.map.now()) > 0;
+		if (!game.map.creature(player).alive() || busy) {
+			term.poll_input(&game.map);
+		}
+
+		if (term.controls.cancel) {
+			term.controls.cancel = false;
+			map::Creature::cancel_action(&mut game.map, player);
+		}
+
+		if (game.map.now() != now || term.redraw) {
+			now = game.map.now();
+			term.draw(&game.map);
+		}
+	}
+
+	io::stdout().write_str("\x1b[0m\x1b[?25h\x1b[2J\x1b[H");
+	raw_mode(false);
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::result;
 
 use sdl;
@@ -6,8 +7,12 @@ use sdl::img;
 use sdl::event;
 use sdl::Rect;
 
-use map;
-use map::MapView;
+use rustyhex::map;
+use rustyhex::map::MapView;
+use rustyhex::messages;
+use rustyhex::input;
+
+use font;
 
 /* replace with something more Rusty
  * in the future */
@@ -29,6 +34,24 @@ static HEX_BORDER_HEIGHT: uint = 5;
 static HEX_FULL_WIDTH: uint = HEX_WIDTH + 2 * HEX_BORDER_WIDTH;
 static HEX_FULL_HEIGHT: uint = HEX_HEIGHT + 2 * HEX_BORDER_HEIGHT;
 
+// Message log panel at the bottom of the screen
+static LOG_LINES: uint = 5;
+static LOG_PADDING: uint = 4;
+static LOG_HEIGHT: uint = LOG_LINES * font::GLYPH_HEIGHT + 2 * LOG_PADDING;
+
+// Player status in the top left corner
+static HUD_WIDTH: uint = 30;
+static HUD_LINES: uint = 5;
+static HUD_PADDING: uint = 4;
+
+// Hexes around the player shown in north up view
+static NORTH_UP_COLUMNS: int = 8;
+static NORTH_UP_ROWS: int = 6;
+
+// Minimap in the top right corner, MINIMAP_CELL pixels per hex
+static MINIMAP_CELL: uint = 6;
+static MINIMAP_MARGIN: uint = 4;
+
 struct Sprite {
 	x : uint,
 	y : uint
@@ -41,13 +64,29 @@ struct View {
 
 pub struct UI {
 	screen : ~video::Surface,
-	player : Option<@mut map::Creature>,
+	player : Option<map::CreatureId>,
 	tiles : ~video::Surface,
+	font : ~font::Font,
 	view : ~View,
-	exit : bool
+	log : @mut messages::MessageLog,
+	// how many messages back the log panel is scrolled
+	log_scroll : uint,
+	minimap : bool,
+	controls : input::Controls,
+	// something on screen changed without game time passing
+	redraw : bool,
+}
+
+// Where a hex ends up on the screen
+trait ToPixels {
+	fn to_pix_x(&self) -> int;
+	fn to_pix_y(&self) -> int;
+	fn to_pix_cx(&self) -> int;
+	fn to_pix_cy(&self) -> int;
+	fn to_rect(&self) -> Rect;
 }
 
-impl map::Position {
+impl ToPixels for map::Position {
 	fn to_pix_x(&self) -> int {
 		self.x * ((HEX_WIDTH - HEX_SIDE_WIDTH) as int) + HEX_BORDER_WIDTH as int
 	}
@@ -78,7 +117,8 @@ impl Sprite {
 	fn for_tile(tile : map::Tile, visible : bool) -> Sprite {
 		let mut spr = match tile {
 				map::FLOOR => Sprite{ x: 0, y: 1 },
-				map::WALL => Sprite{ x: 0, y: 2 }
+				map::WALL => Sprite{ x: 0, y: 2 },
+				map::SWAMP => Sprite{ x: 2, y: 1 }
 			};
 
 		if (!visible) {
@@ -86,13 +126,21 @@ impl Sprite {
 		}
 		spr
 	}
-	fn for_creature(dir : map::Direction) -> Sprite {
-		Sprite{ x: dir.to_uint(), y: 3 }
+	fn for_creature(dir : map::Direction, faction : map::Faction) -> Sprite {
+		let row = match faction {
+			map::ORCS => 3,
+			map::GOBLINS => 4,
+			map::ANIMALS => 5,
+			map::HUMANS => 6
+		};
+		Sprite{ x: dir.to_uint(), y: row }
 	}
 
 	fn for_object(obj : &map::Object) -> Sprite {
 		match obj.get_type() {
-			map::MEDKIT => Sprite{ x: 2, y: 0 }
+			map::MEDKIT => Sprite{ x: 2, y: 0 },
+			map::HERB => Sprite{ x: 3, y: 0 },
+			map::CORPSE => Sprite{ x: 4, y: 0 }
 		}
 	}
 	fn for_hit() -> Sprite {
@@ -113,6 +161,22 @@ impl Sprite {
 	}
 }
 
+fn faction_color(faction : map::Faction) -> video::Color {
+	match faction {
+		map::ORCS => video::RGB(220, 50, 50),
+		map::GOBLINS => video::RGB(50, 200, 50),
+		map::ANIMALS => video::RGB(150, 100, 50),
+		map::HUMANS => video::RGB(70, 120, 255)
+	}
+}
+
+fn fill(screen : &video::Surface, x : int, y : int, w : uint, h : uint, color : video::Color) {
+	let rect = Rect { x: x as i16, y: y as i16, w: w as u16, h: h as u16 };
+	if !screen.fill_rect(Some(rect), color) {
+		fail!(~"Failed fill_rect")
+	}
+}
+
 fn load_or_die(file : ~str) -> ~video::Surface {
 	match img::load(&Path([~"data/", file, ~".png"].concat())) {
 		result::Ok(image) => {
@@ -124,11 +188,41 @@ fn load_or_die(file : ~str) -> ~video::Surface {
 	}
 }
 
+// Frontend independent key, SDL keys are numbered like ASCII
+fn to_key(key : &event::Key) -> Option<input::Key> {
+	let code = *key as uint;
+	match *key {
+		event::UpKey => Some(input::UP),
+		event::DownKey => Some(input::DOWN),
+		event::LeftKey => Some(input::LEFT),
+		event::RightKey => Some(input::RIGHT),
+		event::PageUpKey => Some(input::PAGE_UP),
+		event::PageDownKey => Some(input::PAGE_DOWN),
+		event::EscapeKey => Some(input::ESCAPE),
+		_ if code >= event::Kp0Key as uint && code <= event::Kp9Key as uint => {
+			Some(input::KEYPAD(code - event::Kp0Key as uint))
+		},
+		// number keys too, like in the terminal where they look the same
+		_ if code >= '0' as uint && code <= '9' as uint => Some(input::KEYPAD(code - '0' as uint)),
+		_ if code > 32 && code < 127 => Some(input::CHAR(code as u8 as char)),
+		_ => None
+	}
+}
+
 impl View {
 	fn new(x : int, y : int) -> View {
 		View{ x_offset: x, y_offset: y }
 	}
 
+	// Player is near the bottom when looking ahead, in the middle otherwise
+	fn for_orientation(absolute : bool) -> View {
+		let height = (SCREEN_HEIGHT - LOG_HEIGHT - HEX_FULL_HEIGHT) as int;
+		View::new(
+			(SCREEN_WIDTH - HEX_FULL_WIDTH) as int / 2,
+			if absolute { height / 2 } else { height * 7 / 8 }
+		)
+	}
+
 	fn draw(&self, screen: &video::Surface, pos : map::Position, surface : &video::Surface) {
 		let mut drect = pos.to_rect();
 		drect.x += self.x_offset as i16;
@@ -144,6 +238,41 @@ impl View {
 		) { fail!(~"Failed blit_surface_rect") }
 	}
 
+	// Small square in the middle of the hex
+	fn draw_dot(&self, screen: &video::Surface, pos : map::Position,
+		size : uint, color : video::Color) {
+		let rect = Rect {
+			x: (pos.to_pix_cx() + self.x_offset - (size as int) / 2) as i16,
+			y: (pos.to_pix_cy() + self.y_offset - (size as int) / 2) as i16,
+			w: size as u16,
+			h: size as u16
+		};
+		if !screen.fill_rect(Some(rect), color) {
+			fail!(~"Failed fill_rect")
+		}
+	}
+
+	// Row of colored pips at the top of the hex, one per effect
+	fn draw_effects(&self, screen: &video::Surface, pos : map::Position,
+		effects : &[map::StatusEffect]) {
+		for (i, eff) in effects.iter().enumerate() {
+			let color = match eff.kind {
+				map::POISON => video::RGB(0, 200, 0),
+				map::STUN => video::RGB(230, 230, 0),
+				map::SLOW => video::RGB(60, 120, 255),
+				map::REGENERATION => video::RGB(255, 100, 150)
+			};
+			let rect = Rect {
+				x: (pos.to_pix_x() + self.x_offset + 24 + (i as int) * 10) as i16,
+				y: (pos.to_pix_y() + self.y_offset + 8) as i16,
+				w: 6, h: 6
+			};
+			if !screen.fill_rect(Some(rect), color) {
+				fail!(~"Failed fill_rect")
+			}
+		}
+	}
+
 	fn draw_sprite(&self, dsurf: &video::Surface, ssurf: &video::Surface,
 		pos : map::Position, sprite : Sprite) {
 		let mut drect = pos.to_rect();
@@ -180,29 +309,35 @@ impl UI {
 			};
 
 		let tiles = load_or_die(~"tiles");
+		let font = ~font::Font::new(load_or_die(~"font"));
+		let controls = match input::Controls::load(&Path(input::CONFIG_FILE)) {
+			result::Ok(controls) => controls,
+			result::Err(e) => fail!(e)
+		};
 
 		UI {
 			screen: screen,
 			player: None,
-			exit: false,
-			view: ~View {
-			  x_offset: (SCREEN_WIDTH - HEX_FULL_WIDTH) as int / 2,
-			  y_offset: (SCREEN_HEIGHT - HEX_FULL_HEIGHT) as int * 7 / 8
-			},
+			font: font,
+			log: @mut messages::MessageLog::new(),
+			log_scroll: 0,
+			minimap: false,
+			controls: controls,
+			redraw: false,
+			view: ~View::for_orientation(false),
 			tiles: tiles
 		}
 	}
 
-	pub fn set_player(&mut self, p : @mut map::Creature) {
+	pub fn set_player(&mut self, p : map::CreatureId) {
 		self.player = Some(p);
 	}
-    
-    #[fixed_stack_segment]
-    #[inline(never)]
-	pub fn update(&mut self) {
+
+	pub fn update(&mut self, map : &map::Map) {
+		self.redraw = false;
 
 		let player = match self.player {
-			Some(p) => p,
+			Some(p) => map.creature(p),
 			None => {
 				return;
 			}
@@ -210,9 +345,10 @@ impl UI {
 
 		self.screen.fill(video::RGB(0, 0, 0));
 
-		let mut rm = map::RelativeMap::new(player.map, player.pos, player.dir);
+		let view_dir = self.view_dir(player);
+		let rm = map::RelativeMap::new(map, player.pos, view_dir);
 
-		do player.each_in_view_rect() | pos : map::Position | {
+		let draw_hex : &fn(map::Position) = | pos : map::Position | {
 			let tpos = rm.translate(pos);
 			let base = rm.base();
 			if !player.alive() || player.knows(tpos) {
@@ -228,114 +364,338 @@ impl UI {
 					}
 
 					match base.creature_at(tpos) {
-						Some(creature) => {
-							if (creature.last_hit_time < 8) {
+						Some(id) => {
+							let creature = base.creature(id);
+							if (creature.last_hit_time(map.now()) < 8) {
 								let sprite = Sprite::for_hit();
 								self.view.draw_sprite(self.screen, self.tiles, pos, sprite);
 							}
-							let d = player.dir; // workarounds
-							let cd = creature.dir;
-							let d = cd.relative_to(d);
-							let sprite = Sprite::for_creature(d);
+							let d = creature.dir.relative_to(view_dir);
+							let sprite = Sprite::for_creature(d, creature.faction);
 							self.view.draw_sprite(self.screen, self.tiles, pos, sprite);
+							self.view.draw_effects(self.screen, pos, creature.effects);
 						},
 						None => {}
 					};
+
+					if base.projectile_at(tpos) {
+						self.view.draw_dot(self.screen, pos, 8, video::RGB(255, 220, 0));
+					}
 				}
 			}
+		};
+
+		if self.controls.absolute {
+			map::Position{ x: 0, y: 0 }.each_around(
+				NORTH_UP_ROWS, NORTH_UP_ROWS, NORTH_UP_COLUMNS, NORTH_UP_COLUMNS, draw_hex
+			);
+		} else {
+			player.each_in_view_rect(draw_hex);
 		}
 
 		if (player.alive()) {
-			self.view.draw_sprite(self.screen, self.tiles, map::Position {x:0, y:0}, Sprite::human());
+			let sprite = if self.controls.absolute {
+				Sprite::for_creature(player.dir, player.faction)
+			} else {
+				Sprite::human()
+			};
+			self.view.draw_sprite(self.screen, self.tiles, map::Position {x:0, y:0}, sprite);
+			self.view.draw_effects(self.screen, map::Position {x:0, y:0}, player.effects);
+		}
+
+		self.draw_hud(map, player);
+		if !player.alive() {
+			self.draw_death();
+		}
+		if self.minimap {
+			self.draw_minimap(map, player);
 		}
+		self.draw_log();
+
+		let clock = map.clock;
+		sdl::wm::set_caption(
+			fmt!("rustyhex - turn %u, %s", clock.turns, clock.to_str()),
+			"rustyhex"
+		);
 
 		self.screen.flip();
+	}
 
+	// Let a tick of game time pass in real time
+	#[fixed_stack_segment]
+	#[inline(never)]
+	pub fn wait_tick(&self) {
 		unsafe {
-			usleep(1000);
+			usleep((1000000 / map::TICKS_PER_SECOND) as c_int);
 		}
 	}
 
-	pub fn keyevent_to_action(&mut self, key : &event::Key, m : &[event::Mod] ) -> Option<map::Action> {
-		let attack = m.contains(&event::LCtrlMod);
-		let strafe = m.contains(&event::LShiftMod);
-		let dir = match *key {
-			event::KKey | event::UpKey => {
-				Some(map::FORWARD)
-			},
-			event::JKey | event::DownKey => {
-				Some(map::BACKWARD)
-			},
-			event::HKey | event::LeftKey => {
-				Some(map::LEFT)
-			},
-			event::LKey | event::RightKey => {
-				Some(map::RIGHT)
-			},
-			_ => None
+	fn draw_hud(&self, map : &map::Map, player : &map::Creature) {
+		let panel = Rect {
+			x: 0, y: 0,
+			w: (HUD_WIDTH * font::GLYPH_WIDTH + 2 * HUD_PADDING) as u16,
+			h: (HUD_LINES * font::GLYPH_HEIGHT + 2 * HUD_PADDING) as u16
 		};
-		match *key {
-			event::EscapeKey => {
-				self.exit = true;
-				return Some(map::WAIT);
-			},
-			event::PeriodKey | event::CommaKey => {
-				return Some(map::WAIT);
-			},
-			event::UKey => {
-				return Some(map::USE)
+		if !self.screen.fill_rect(Some(panel), video::RGB(0, 0, 0)) {
+			fail!(~"Failed fill_rect")
+		}
+
+		let action = match player.action {
+			Some(a) if player.alive() => {
+				fmt!("%s (%u)", a.to_str(), player.pre_action_ticks(map.now()))
 			},
-			_ => {}
+			_ => ~"-"
 		};
-		match (dir, strafe, attack) {
-			(Some(d), _, true) => {
-				Some(map::MELEE(d))
-			},
-			(Some(d), true, _) => {
-				if (d == map::FORWARD) {
-					Some(map::RUN(d))
-				} else {
-					Some(map::MOVE(d))
-				}
-			},
-			(Some(d), false, _) => {
-				match (d) {
-					map::FORWARD|map::BACKWARD => {
-						Some(map::MOVE(d))
-					},
-					__=> {
-						Some(map::TURN(d))
-					}
-				}
-			},
-			_ => None
+		let life_color = if player.life * 3 <= player.max_life { font::RED } else { font::WHITE };
+		let lines = [
+			(fmt!("Life:  %d/%d", cmp::max(player.life, 0), player.max_life), life_color),
+			(fmt!("Doing: %s", action), font::WHITE),
+			(fmt!("Kills: %u", player.kills), font::WHITE),
+			(fmt!("Turn:  %u (%s)", map.clock.turns, map.clock.to_str()), font::WHITE)
+		];
+		for (i, &(ref line, color)) in lines.iter().enumerate() {
+			let y = (HUD_PADDING + i * font::GLYPH_HEIGHT) as int;
+			self.font.draw(self.screen, HUD_PADDING as int, y, *line, color);
 		}
+
+		// status effects on the last line, each in a color of its own
+		let y = (HUD_PADDING + lines.len() * font::GLYPH_HEIGHT) as int;
+		let mut x = HUD_PADDING as int;
+		let label = "Under: ";
+		self.font.draw(self.screen, x, y, label, font::WHITE);
+		x += self.font.width(label) as int;
+		if player.effects.is_empty() || !player.alive() {
+			self.font.draw(self.screen, x, y, "-", font::WHITE);
+			return;
+		}
+		for eff in player.effects.iter() {
+			let (name, color) = match eff.kind {
+				map::POISON => ("poison ", font::GREEN),
+				map::STUN => ("stun ", font::YELLOW),
+				map::SLOW => ("slow ", font::YELLOW),
+				map::REGENERATION => ("regen ", font::BLUE)
+			};
+			self.font.draw(self.screen, x, y, name, color);
+			x += self.font.width(name) as int;
+		}
+	}
+
+	// Over the middle of the map once the player is gone
+	fn draw_death(&self) {
+		let text = "You have died. Press Escape to leave, or scroll the log \
+			with Page Up and Page Down to see how it happened.";
+		let width = SCREEN_WIDTH / 2;
+		let x = (SCREEN_WIDTH / 4) as int;
+		let y = ((SCREEN_HEIGHT - LOG_HEIGHT) / 2) as int;
+		let lines = self.font.wrap(text, width);
+		let panel = Rect {
+			x: (x - HUD_PADDING as int) as i16, y: (y - HUD_PADDING as int) as i16,
+			w: (width + 2 * HUD_PADDING) as u16,
+			h: (lines.len() * font::GLYPH_HEIGHT + 2 * HUD_PADDING) as u16
+		};
+		if !self.screen.fill_rect(Some(panel), video::RGB(0, 0, 0)) {
+			fail!(~"Failed fill_rect")
+		}
+		self.font.draw_wrapped(self.screen, x, y, text, font::RED, width);
 	}
 
-	pub fn check_exit_input(&mut self) {
-		match event::poll_event() {
-			event::KeyEvent(key, true , _, _) => {
-				match (key) {
-					event::EscapeKey => {
-						self.exit = true;
-					},
-					_ => {}
+	// The whole map as the player knows it, north up
+	fn draw_minimap(&self, map : &map::Map, player : &map::Creature) {
+		let width = map.width * MINIMAP_CELL;
+		// the last of the 2 * height half rows reaches a full cell down
+		let height = (2 * map.height + 1) * MINIMAP_CELL / 2;
+		let left = (SCREEN_WIDTH - width - MINIMAP_MARGIN) as int;
+		let top = MINIMAP_MARGIN as int;
+
+		// columns of hexes are staggered by half a cell
+		let cell_at = |pos : map::Position| {
+			let p = map.wrap_position(pos);
+			let rows = 2 * map.height as int;
+			let row = ((2 * p.y - p.x) % rows + rows) % rows;
+			(left + p.x * MINIMAP_CELL as int, top + row * (MINIMAP_CELL as int) / 2)
+		};
+
+		fill(self.screen, left, top, width, height, video::RGB(0, 0, 0));
+
+		for x in range(0, map.width as int) {
+			for y in range(0, map.height as int) {
+				let pos = map::Position { x: x, y: y };
+				if !player.knows(pos) {
+					loop;
 				}
+				let (r, g, b) = match map.at(pos) {
+					map::FLOOR => (110, 90, 60),
+					map::WALL => (170, 170, 170),
+					map::SWAMP => (60, 130, 60)
+				};
+				let color = if player.sees(pos) {
+					video::RGB(r, g, b)
+				} else {
+					video::RGB(r / 2, g / 2, b / 2)
+				};
+				let (px, py) = cell_at(pos);
+				fill(self.screen, px, py, MINIMAP_CELL, MINIMAP_CELL, color);
+			}
+		}
+
+		do map.for_each_creature |cr| {
+			if !cr.is_player() && player.sees(cr.pos) {
+				let (px, py) = cell_at(cr.pos);
+				fill(self.screen, px + 1, py + 1, MINIMAP_CELL - 2, MINIMAP_CELL - 2,
+					faction_color(cr.faction));
+			}
+		}
+
+		if player.alive() {
+			let (px, py) = cell_at(player.pos);
+			fill(self.screen, px, py, MINIMAP_CELL, MINIMAP_CELL, video::RGB(255, 255, 255));
+			let (fx, fy) = cell_at(player.pos.neighbor(player.dir));
+			fill(self.screen, fx + 1, fy + 1, MINIMAP_CELL - 2, MINIMAP_CELL - 2,
+				video::RGB(255, 220, 0));
+		}
+	}
+
+	// Direction that is up on the screen
+	fn view_dir(&self, player : &map::Creature) -> map::Direction {
+		if self.controls.absolute { map::N } else { player.dir }
+	}
+
+	fn toggle_minimap(&mut self) {
+		self.minimap = !self.minimap;
+		self.redraw = true;
+	}
+
+	fn draw_log(&self) {
+		let top = (SCREEN_HEIGHT - LOG_HEIGHT) as int;
+		let panel = Rect {
+			x: 0, y: top as i16,
+			w: SCREEN_WIDTH as u16, h: LOG_HEIGHT as u16
+		};
+		if !self.screen.fill_rect(Some(panel), video::RGB(0, 0, 0)) {
+			fail!(~"Failed fill_rect")
+		}
+
+		// long messages take more than one line, with the time stamp
+		// on the first one only
+		let mut lines = ~[];
+		for msg in self.log.last(LOG_LINES, self.log_scroll).iter() {
+			let stamp = fmt!("[%s] ", msg.time.to_str());
+			let indent = self.font.width(stamp);
+			let wrapped = self.font.wrap(msg.text, SCREEN_WIDTH - 2 * LOG_PADDING - indent);
+			for (i, text) in wrapped.move_iter().enumerate() {
+				let stamp = if i == 0 { Some(stamp.clone()) } else { None };
+				lines.push((stamp, indent, text));
+			}
+		}
+
+		let first = lines.len() - cmp::min(LOG_LINES, lines.len());
+		for (i, &(ref stamp, indent, ref text)) in lines.slice_from(first).iter().enumerate() {
+			let x = LOG_PADDING as int;
+			let y = top + (LOG_PADDING + i * font::GLYPH_HEIGHT) as int;
+			match *stamp {
+				Some(ref s) => self.font.draw(self.screen, x, y, *s, font::GREY),
+				None => {}
+			}
+			self.font.draw(self.screen, x + indent as int, y, *text, font::WHITE);
+		}
+	}
+
+	// Positive `lines` go back to older messages
+	fn scroll_log(&mut self, lines : int) {
+		let scroll = self.log.scroll(self.log_scroll, lines, LOG_LINES);
+		if scroll != self.log_scroll {
+			self.log_scroll = scroll;
+			self.redraw = true;
+		}
+	}
+
+	// Bring the screen up to date with a command
+	fn show(&mut self, command : input::Command) {
+		match command {
+			input::MINIMAP => self.toggle_minimap(),
+			input::ORIENTATION => {
+				self.view = ~View::for_orientation(self.controls.absolute);
+				self.redraw = true;
 			},
+			input::SCROLL(lines) => self.scroll_log(lines),
 			_ => {}
 		}
 	}
 
-	pub fn get_input(&mut self) -> map::Action {
+	// Frontend independent key for an SDL key event
+	fn to_input(key : &event::Key, m : &[event::Mod]) -> Option<(input::Key, input::Modifiers)> {
+		let mods = input::Modifiers {
+			ctrl: m.contains(&event::LCtrlMod),
+			alt: m.contains(&event::LAltMod),
+			shift: m.contains(&event::LShiftMod)
+		};
+		to_key(key).map(|&k| (k, mods))
+	}
+
+	fn key_to_action(&mut self, key : input::Key, mods : input::Modifiers,
+			map : &map::Map, player : map::CreatureId) -> Option<map::Action> {
+		match self.controls.press(key, mods, map, player, false) {
+			Some(input::ACT(a)) => Some(a),
+			Some(command) => {
+				self.show(command);
+				None
+			},
+			None => None
+		}
+	}
+
+	pub fn keyevent_to_action(&mut self, key : &event::Key, m : &[event::Mod],
+			map : &map::Map, player : map::CreatureId) -> Option<map::Action> {
+		match UI::to_input(key, m) {
+			Some((k, mods)) => self.key_to_action(k, mods, map, player),
+			None => None
+		}
+	}
+
+	// Look for keys that matter while the player is not deciding,
+	// keeping the actions for when they are
+	pub fn poll_input(&mut self, map : &map::Map) {
+		let player = match self.player {
+			Some(p) => p,
+			None => return
+		};
+		let alive = map.creature(player).alive();
+		loop {
+			let typed = match event::poll_event() {
+				event::KeyEvent(key, true , m, _) => UI::to_input(&key, m),
+				event::NoEvent => return,
+				_ => None
+			};
+			// the dead have nothing to type ahead for
+			let command = match typed {
+				Some((k, mods)) => self.controls.press(k, mods, map, player, alive),
+				None => None
+			};
+			match command {
+				Some(c) => self.show(c),
+				None => {}
+			}
+		}
+	}
+
+	pub fn get_input(&mut self, map : &map::Map) -> map::Action {
+		let player = self.player.unwrap();
+		match self.controls.typed_action(map, player) {
+			Some(a) => return a,
+			None => {}
+		}
 		loop {
 			match event::wait_event() {
 				event::KeyEvent(key, true , m, _) => {
-					match self.keyevent_to_action(&key, m) {
+					match self.keyevent_to_action(&key, m, map, player) {
 						Some(a) => {
 							return a;
 						},
 						None => {}
 					}
+					if self.redraw {
+						self.update(map);
+					}
 				},
 				event::NoEvent => {},
 				_ => {}